@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_outcomes(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    recorded_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
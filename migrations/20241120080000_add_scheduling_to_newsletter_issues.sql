@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE
+    newsletter_issues
+ADD
+    COLUMN status TEXT NOT NULL DEFAULT 'published',
+ADD
+    COLUMN scheduled_for timestamptz NULL,
+ALTER
+    COLUMN published_at DROP NOT NULL;
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE
+    users
+ADD
+    COLUMN email TEXT NULL;
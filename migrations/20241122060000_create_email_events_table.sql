@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE email_events(
+    email_event_id uuid NOT NULL,
+    provider TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    event_type TEXT NULL,
+    email TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    payload JSONB NOT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY (email_event_id)
+);
+CREATE INDEX email_events_email_idx ON email_events (email);
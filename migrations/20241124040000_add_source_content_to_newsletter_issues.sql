@@ -0,0 +1,26 @@
+-- Add migration script here
+BEGIN;
+
+ALTER TABLE
+    newsletter_issues
+ADD
+    COLUMN content_format TEXT NOT NULL DEFAULT 'html',
+ADD
+    COLUMN source_content TEXT NULL,
+ADD
+    COLUMN source_text_content TEXT NULL;
+
+UPDATE
+    newsletter_issues
+SET
+    source_content = html_content,
+    source_text_content = text_content;
+
+ALTER TABLE
+    newsletter_issues
+ALTER COLUMN
+    source_content
+SET
+    NOT NULL;
+
+COMMIT;
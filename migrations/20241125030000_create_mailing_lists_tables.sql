@@ -0,0 +1,57 @@
+-- Add migration script here
+BEGIN;
+
+CREATE TABLE mailing_lists(
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id)
+);
+
+-- Subscriptions without a list id join the default list.
+CREATE UNIQUE INDEX mailing_lists_single_default ON mailing_lists (is_default)
+WHERE
+    is_default;
+
+CREATE TABLE list_memberships(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES mailing_lists (list_id) ON DELETE CASCADE,
+    joined_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id, list_id)
+);
+
+CREATE TABLE newsletter_issue_lists(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL REFERENCES mailing_lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+-- Everyone who was subscribed so far was subscribed to the one newsletter.
+INSERT INTO
+    mailing_lists (list_id, name, is_default)
+VALUES
+    (gen_random_uuid(), 'Newsletter', true);
+
+INSERT INTO
+    list_memberships (subscriber_id, list_id)
+SELECT
+    s.id,
+    l.list_id
+FROM
+    subscriptions s
+    CROSS JOIN mailing_lists l;
+
+INSERT INTO
+    newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT
+    i.newsletter_issue_id,
+    l.list_id
+FROM
+    newsletter_issues i
+    CROSS JOIN mailing_lists l
+WHERE
+    i.status <> 'draft';
+
+COMMIT;
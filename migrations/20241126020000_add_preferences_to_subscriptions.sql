@@ -0,0 +1,15 @@
+-- Add migration script here
+BEGIN;
+
+ALTER TABLE
+    subscriptions
+ADD
+    COLUMN delivery_format TEXT NOT NULL DEFAULT 'html';
+
+-- Set on tokens confirming a change of address rather than a sign-up.
+ALTER TABLE
+    subscription_tokens
+ADD
+    COLUMN new_email TEXT NULL;
+
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE signup_attempts(
+    ip_address TEXT NULL,
+    email TEXT NOT NULL,
+    attempted_at timestamptz NOT NULL
+);
+CREATE INDEX signup_attempts_ip_address_idx ON signup_attempts (ip_address, attempted_at);
+CREATE INDEX signup_attempts_email_idx ON signup_attempts (email, attempted_at);
@@ -0,0 +1,24 @@
+-- Add migration script here
+BEGIN;
+ALTER TABLE
+    users
+ADD
+    COLUMN role TEXT NOT NULL DEFAULT 'owner',
+ADD
+    COLUMN is_active BOOLEAN NOT NULL DEFAULT true;
+-- Existing users keep full access, new ones get the role they were invited with.
+ALTER TABLE
+    users
+ALTER COLUMN
+    role DROP DEFAULT;
+CREATE TABLE user_invitations(
+    invitation_id uuid NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    invited_by uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    PRIMARY KEY (invitation_id)
+);
+COMMIT;
@@ -0,0 +1,16 @@
+-- Add migration script here
+BEGIN;
+CREATE TABLE password_reset_tokens(
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (token_hash)
+);
+-- Sessions remember the epoch they were opened in, bumping it logs the user
+-- out everywhere.
+ALTER TABLE
+    users
+ADD
+    COLUMN session_epoch INTEGER NOT NULL DEFAULT 0;
+COMMIT;
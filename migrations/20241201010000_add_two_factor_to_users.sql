@@ -0,0 +1,15 @@
+-- Add migration script here
+BEGIN;
+ALTER TABLE
+    users
+ADD
+    COLUMN totp_secret TEXT NULL,
+ADD
+    COLUMN totp_last_step BIGINT NULL;
+CREATE TABLE recovery_codes(
+    code_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    used_at timestamptz NULL,
+    PRIMARY KEY (code_hash)
+);
+COMMIT;
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Set on tokens confirming that an already confirmed subscriber joins
+-- another list.
+ALTER TABLE
+    subscription_tokens
+ADD
+    COLUMN list_id uuid NULL REFERENCES mailing_lists (list_id) ON DELETE CASCADE;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Proof of work challenges already used for a signup, so they can't be
+-- replayed while still valid.
+CREATE TABLE proof_of_work_redemptions(
+    salt TEXT NOT NULL,
+    redeemed_at timestamptz NOT NULL,
+    PRIMARY KEY (salt)
+);
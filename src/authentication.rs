@@ -1,3 +1,5 @@
+use std::sync::LazyLock;
+
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts, RequestPartsExt};
 use axum_extra::{
@@ -5,11 +7,25 @@ use axum_extra::{
     TypedHeader,
 };
 use base64::{prelude::BASE64_STANDARD_NO_PAD, Engine};
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::{errors::Error, telemetry::spawn_blocking_with_tracing, Result};
+use unicode_segmentation::UnicodeSegmentation;
+use zxcvbn::zxcvbn;
+
+use crate::{
+    configuration::PasswordPolicySettings,
+    domain::{is_breached_password, TotpSecret},
+    errors::Error,
+    telemetry::spawn_blocking_with_tracing,
+    Result,
+};
+
+const N_RECOVERY_CODES: usize = 10;
 
 #[derive(Debug)]
 pub struct Credentials {
@@ -71,37 +87,39 @@ where
     }
 }
 
+/// Unknown usernames are checked against this hash, so they take as long to
+/// reject as wrong passwords and can't be told apart by timing.
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    compute_password_hash(Secret::new(Uuid::new_v4().to_string()))
+        .expect("Failed to hash the dummy password")
+        .expose_secret()
+        .clone()
+});
+
+/// Computes the dummy hash up front, as hashing it during a login would make
+/// that one rejection slower than the others.
+pub fn init_dummy_password_hash() {
+    LazyLock::force(&DUMMY_PASSWORD_HASH);
+}
+
 pub async fn validate_credentials(credentials: Credentials, pool: &PgPool) -> Result<Uuid> {
     let row = get_stored_credentials(&credentials.username, pool).await?;
 
     let (expected_password_hash, user_id) = match row {
-        Some(user) => (user.password_hash, user.user_id),
-        None => {
-            return Err(Error::Unauthorized(
-                "Failed to query to retrieve stored credentials.".to_string(),
-            ))
-        }
+        Some(user) => (Some(user.password_hash), Some(user.user_id)),
+        None => (None, None),
     };
     let task_res = spawn_blocking_with_tracing(move || {
+        let expected_password_hash =
+            expected_password_hash.unwrap_or_else(|| DUMMY_PASSWORD_HASH.clone());
         verify_password_hash(expected_password_hash, credentials.password)
     })
     .await;
+    let invalid = || Error::Unauthorized("Invalid username or password.".to_string());
     match task_res {
-        Ok(verify_res) => {
-            if verify_res.is_err() {
-                return Err(Error::Unauthorized(
-                    "Failed to verify password hash.".to_string(),
-                ));
-            }
-        }
-        Err(_) => {
-            return Err(Error::Unauthorized(
-                "Failed to verify password hash.".to_string(),
-            ))
-        }
+        Ok(Ok(())) => user_id.ok_or_else(invalid),
+        Ok(Err(_)) | Err(_) => Err(invalid()),
     }
-
-    Ok(user_id)
 }
 
 #[derive(sqlx::FromRow)]
@@ -115,7 +133,7 @@ async fn get_stored_credentials(username: &str, pool: &PgPool) -> Result<Option<
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND is_active
         "#,
     )
     .bind(username)
@@ -136,14 +154,14 @@ fn verify_password_hash(
     Ok(())
 }
 
+/// Stores a password hashed with [`hash_password`], which is best done before
+/// the transaction begins, hashing being slow.
 pub async fn change_password_store(
+    transaction: &mut Transaction<'_, Postgres>,
     user_id: Uuid,
-    password: Secret<String>,
-    pool: &PgPool,
+    password_hash: Secret<String>,
 ) -> Result<()> {
-    let password_hash =
-        spawn_blocking_with_tracing(move || compute_password_hash(password)).await??;
-    sqlx::query(
+    let query = sqlx::query(
         r#"
         UPDATE users
         SET password_hash = $1
@@ -151,12 +169,61 @@ pub async fn change_password_store(
         "#,
     )
     .bind(password_hash.expose_secret())
-    .bind(user_id)
-    .execute(pool)
-    .await?;
+    .bind(user_id);
+    transaction.execute(query).await?;
     Ok(())
 }
 
+/// Hashes a password off the async runtime, hashing being slow on purpose.
+pub async fn hash_password(password: Secret<String>) -> Result<Secret<String>> {
+    spawn_blocking_with_tracing(move || compute_password_hash(password)).await?
+}
+
+/// Why `password` doesn't meet the policy, if it doesn't, in words fit to show
+/// the user. `user_inputs` are things like the username and email, which make
+/// a password easier to guess when it contains them.
+pub async fn password_policy_violation(
+    policy: &PasswordPolicySettings,
+    password: &Secret<String>,
+    user_inputs: &[&str],
+) -> Result<Option<String>> {
+    let length = password.expose_secret().graphemes(true).count();
+    if length < policy.min_length {
+        return Ok(Some(format!(
+            "The password must be at least {} characters long.",
+            policy.min_length
+        )));
+    }
+    if length > policy.max_length {
+        return Ok(Some(format!(
+            "The password must be at most {} characters long.",
+            policy.max_length
+        )));
+    }
+    let strength = zxcvbn(password.expose_secret(), user_inputs).score() as u8;
+    if strength < policy.min_strength {
+        return Ok(Some(
+            "The password is too easy to guess - try a longer passphrase, and avoid common \
+             words, sequences and your own name."
+                .to_string(),
+        ));
+    }
+    if let Some(path) = policy.breached_passwords_file.clone() {
+        let password = password.clone();
+        let is_breached = spawn_blocking_with_tracing(move || {
+            is_breached_password(path.as_ref(), password.expose_secret())
+        })
+        .await??;
+        if is_breached {
+            return Ok(Some(
+                "The password has appeared in a data breach, please choose another one."
+                    .to_string(),
+            ));
+        }
+    }
+    Ok(None)
+}
+
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
     let slat = SaltString::generate(&mut rand::thread_rng());
     let password = Argon2::default()
@@ -164,3 +231,157 @@ fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
         .to_string();
     Ok(Secret::new(password))
 }
+
+/// One-time tokens sent by email are stored hashed, so a leaked database
+/// doesn't hand out accounts.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The epoch sessions of the user must be opened in to stay valid.
+pub async fn get_session_epoch(user_id: Uuid, pool: &PgPool) -> Result<i32> {
+    let row: (i32,) = sqlx::query_as(
+        r#"
+        SELECT session_epoch FROM users WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.0)
+}
+
+pub async fn get_totp_secret(user_id: Uuid, pool: &PgPool) -> Result<Option<TotpSecret>> {
+    let row: (Option<String>,) = sqlx::query_as(
+        r#"
+        SELECT totp_secret FROM users WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    row.0.as_deref().map(TotpSecret::parse_base32).transpose()
+}
+
+/// Checks a code from the user's authenticator app, or one of their unused
+/// recovery codes. Either can only be used once.
+pub async fn verify_second_factor(user_id: Uuid, code: &str, pool: &PgPool) -> Result<bool> {
+    let row: (Option<String>, Option<i64>) = sqlx::query_as(
+        r#"
+        SELECT totp_secret, totp_last_step FROM users WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    let (Some(secret), last_step) = row else {
+        return Ok(false);
+    };
+    let secret = TotpSecret::parse_base32(&secret)?;
+    if let Some(step) = secret.verify(code, Utc::now(), last_step) {
+        // Guarded by the previous step, so a code raced in twice only
+        // passes once.
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET totp_last_step = $2
+            WHERE user_id = $1 AND totp_last_step IS NOT DISTINCT FROM $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .bind(last_step)
+        .execute(pool)
+        .await?;
+        return Ok(result.rows_affected() == 1);
+    }
+    let result = sqlx::query(
+        r#"
+        UPDATE recovery_codes SET used_at = now()
+        WHERE code_hash = $1 AND user_id = $2 AND used_at IS NULL
+        "#,
+    )
+    .bind(hash_token(&code.trim().to_lowercase()))
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Turns on two-factor authentication with a secret the user has proven to
+/// have set up, returning a fresh set of recovery codes. Only their hashes
+/// are kept.
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &TotpSecret,
+    step: i64,
+    pool: &PgPool,
+) -> Result<Vec<String>> {
+    let mut transaction = pool.begin().await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE users SET totp_secret = $2, totp_last_step = $3 WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(secret.base32())
+    .bind(step);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM recovery_codes WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id);
+    transaction.execute(query).await?;
+    let codes = (0..N_RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<_>>();
+    let query = sqlx::query(
+        r#"
+        INSERT INTO recovery_codes (code_hash, user_id)
+        SELECT code_hash, $2 FROM UNNEST($1::text[]) AS t(code_hash)
+        "#,
+    )
+    .bind(
+        codes
+            .iter()
+            .map(|code| hash_token(code))
+            .collect::<Vec<_>>(),
+    )
+    .bind(user_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(codes)
+}
+
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE users SET totp_secret = NULL, totp_last_step = NULL WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM recovery_codes WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// A code like `k3x9a-0mq2f`, easy to copy down by hand.
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let mut part = || {
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .take(5)
+            .collect::<String>()
+    };
+    format!("{}-{}", part(), part())
+}
@@ -1,9 +1,16 @@
+use std::num::NonZeroU16;
+
+use rand::Rng;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient, telemetry, Result};
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{EmailClient, FileTransport, PostmarkTransport, SmtpTransport},
+    telemetry, Result,
+};
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
@@ -63,16 +70,64 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub subscriptions: SubscriptionSettings,
+    pub login: LoginSettings,
+    pub password_policy: PasswordPolicySettings,
     pub logger: LoggerSettings,
     pub redis_uri: Secret<String>,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file: Option<FileSinkSettings>,
+    pub webhook: WebhookSettings,
+}
+
+/// Basic auth credentials the email provider uses to call our webhooks.
+#[derive(Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub tls: SmtpTls,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    None,
+    Starttls,
+    Tls,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FileSinkSettings {
+    pub path: String,
 }
 
 impl EmailClientSettings {
@@ -86,21 +141,157 @@ impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
+        match self.transport {
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailTransportKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("Missing `email_client.smtp` settings for the SMTP transport.");
+                EmailClient::new(
+                    sender_email,
+                    SmtpTransport::new(&smtp, timeout).expect("Invalid SMTP settings."),
+                )
+            }
+            EmailTransportKind::File => {
+                let file = self
+                    .file
+                    .expect("Missing `email_client.file` settings for the file transport.");
+                EmailClient::new(sender_email, FileTransport::new(file.path))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    /// Emails sent per batch; a batch of 0 would never deliver anything.
+    pub batch_size: NonZeroU16,
+    pub max_retries: i16,
+    pub base_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+}
+
+impl IssueDeliverySettings {
+    /// Exponential backoff capped at `max_backoff_milliseconds`, with the
+    /// lower half jittered so retries of a failed batch don't fire in lockstep.
+    pub fn backoff(&self, n_retries: i16) -> std::time::Duration {
+        let exponential = self
+            .base_backoff_milliseconds
+            .saturating_mul(2u64.saturating_pow(n_retries.max(0) as u32));
+        let ceiling = exponential.min(self.max_backoff_milliseconds);
+        let jittered = rand::thread_rng().gen_range(ceiling / 2..=ceiling);
+        std::time::Duration::from_millis(jittered)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    pub confirmation_token_ttl_hours: u32,
+    pub expired_token_retention_hours: u32,
+    pub max_signups_per_ip_per_hour: u32,
+    pub max_signups_per_email_per_hour: u32,
+    /// How long the emailed links to download or erase a subscriber's data
+    /// stay valid.
+    pub data_link_ttl_minutes: u32,
+    /// Leading zero bits a proof of work needs before a signup is accepted;
+    /// 0 turns the check off.
+    #[serde(default)]
+    pub proof_of_work_difficulty: u8,
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.confirmation_token_ttl_hours.into())
+    }
+
+    pub fn data_link_ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.data_link_ttl_minutes.into())
+    }
+
+    /// How long a proof of work challenge can be solved for.
+    pub fn proof_of_work_ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(10)
+    }
+
+    /// Tokens are kept past their expiry for a while, so a late click still
+    /// gets told its link expired rather than that it is unknown.
+    pub fn token_purge_age(&self) -> chrono::Duration {
+        chrono::Duration::hours(
+            i64::from(self.confirmation_token_ttl_hours)
+                + i64::from(self.expired_token_retention_hours),
         )
     }
 }
 
+#[derive(Deserialize, Clone)]
+pub struct LoginSettings {
+    pub max_failures_per_username: u32,
+    pub max_failures_per_ip: u32,
+    pub failure_window_minutes: u32,
+    pub lockout_minutes: u32,
+    pub failures_before_delay: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub max_password_resets_per_email: u32,
+    pub max_password_resets_per_ip: u32,
+    pub password_reset_window_minutes: u32,
+}
+
+impl LoginSettings {
+    /// How long a failed login counts towards the limits.
+    pub fn failure_window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(u64::from(self.failure_window_minutes) * 60)
+    }
+
+    pub fn lockout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(u64::from(self.lockout_minutes) * 60)
+    }
+
+    /// How long a password reset request counts towards the limits.
+    pub fn password_reset_window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(u64::from(self.password_reset_window_minutes) * 60)
+    }
+
+    /// The wait before answering the `n_failures`th failed login in a row,
+    /// doubling with each failure past `failures_before_delay`.
+    pub fn delay(&self, n_failures: u32) -> std::time::Duration {
+        let Some(n_delayed) = n_failures.checked_sub(self.failures_before_delay + 1) else {
+            return std::time::Duration::ZERO;
+        };
+        let delay = self
+            .base_delay_milliseconds
+            .saturating_mul(2u64.saturating_pow(n_delayed));
+        std::time::Duration::from_millis(delay.min(self.max_delay_milliseconds))
+    }
+}
+
+/// What new passwords must satisfy, wherever a user picks one.
+#[derive(Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// zxcvbn score, from 0 (too guessable) to 4 (very unguessable).
+    pub min_strength: u8,
+    /// Local copy of the Pwned Passwords list; breached passwords aren't
+    /// checked without one.
+    #[serde(default)]
+    pub breached_passwords_file: Option<String>,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct ApplicationSettings {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// Whether a reverse proxy sits in front of the app and appends the
+    /// client address to `X-Forwarded-For`.
+    #[serde(default)]
+    pub behind_proxy: bool,
 }
 
 impl ApplicationSettings {
@@ -1,5 +1,5 @@
-use crate::{controller::format, startup::AppState, Result};
-use axum::{debug_handler, extract::State, response::Response};
+use crate::{controller::format, domain::Role, startup::AppState, Result};
+use axum::{debug_handler, extract::State, response::Response, Extension};
 use axum_session::Session;
 use axum_session_redispool::SessionRedisPool;
 use serde_json::json;
@@ -9,6 +9,7 @@ use uuid::Uuid;
 #[debug_handler]
 pub async fn admin_dashboard(
     session: Session<SessionRedisPool>,
+    Extension(role): Extension<Role>,
     State(state): State<AppState>,
 ) -> Result<Response> {
     let user_id = session.get("user_id");
@@ -21,7 +22,11 @@ pub async fn admin_dashboard(
     format::render().view(
         &state.tera_engine,
         "admin/dashboard.html",
-        json!({"username": username}),
+        json!({
+            "username": username,
+            "role": role.as_str(),
+            "is_owner": role == Role::Owner,
+        }),
     )
 }
 
@@ -0,0 +1,39 @@
+use axum::{debug_handler, extract::State, response::Response, Extension};
+use axum_messages::Messages;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{controller::format, startup::AppState, Result};
+
+#[debug_handler]
+pub async fn change_email_form(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    let email = get_user_email(user_id, &state.db_pool).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/email.html",
+        json!({"messages": messages, "email": email}),
+    )
+}
+
+pub async fn get_user_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>> {
+    let row: (Option<String>,) = sqlx::query_as(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.0)
+}
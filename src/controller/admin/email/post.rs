@@ -0,0 +1,95 @@
+use axum::{debug_handler, extract::State, response::Response, Extension, Form};
+use axum_messages::Messages;
+use secrecy::Secret;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{validate_credentials, Credentials},
+    controller::{admin::dashboard::get_username, format},
+    domain::SubscriberEmail,
+    startup::AppState,
+    Result,
+};
+
+use super::get_user_email;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    current_password: Secret<String>,
+}
+
+/// Password reset links go to this address, so changing it takes the
+/// current password and the old address is told about it.
+#[debug_handler]
+pub async fn change_email(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<FormData>,
+) -> Result<Response> {
+    let email = match SubscriberEmail::parse(params.email) {
+        Ok(email) => email,
+        Err(e) => {
+            messages.error(e.to_string());
+            return format::render().redirect("/admin/email");
+        }
+    };
+    let username = get_username(user_id, &state.db_pool).await?;
+    let credentials = Credentials {
+        username: username.clone(),
+        password: params.current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &state.db_pool).await {
+        messages.error(e.to_string());
+        return format::render().redirect("/admin/email");
+    };
+    let old_email = get_user_email(user_id, &state.db_pool).await?;
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET email = $2
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(email.as_ref())
+    .execute(state.db_pool.as_ref())
+    .await?;
+    if let Some(old_email) = old_email.filter(|old_email| old_email != email.as_ref()) {
+        if let Err(e) = send_email_changed_email(&state, &old_email, &username, &email).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the email changed notice",
+            );
+        }
+    }
+    messages.info("Your email address has been updated.");
+    format::render().redirect("/admin/email")
+}
+
+/// Lets the admin know, at the address they had, that it was replaced.
+async fn send_email_changed_email(
+    state: &AppState,
+    old_email: &str,
+    username: &str,
+    new_email: &SubscriberEmail,
+) -> Result<()> {
+    let body = state.tera_engine.render_email(
+        "email_changed",
+        json!({"username": username, "new_email": new_email.as_ref()}),
+    )?;
+    state
+        .email_client
+        .send_email(
+            SubscriberEmail::parse(old_email.to_string())?,
+            "Your email address was changed",
+            &body.html,
+            &body.text,
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use axum::{debug_handler, extract::State, response::Response};
+use axum_messages::Messages;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{controller::format, startup::AppState, Result};
+
+#[derive(FromRow, Serialize)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub is_default: bool,
+    pub n_subscribers: i64,
+}
+
+#[debug_handler]
+pub async fn mailing_lists(messages: Messages, State(state): State<AppState>) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    let lists = get_mailing_lists(&state.db_pool).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/lists.html",
+        json!({"messages": messages, "lists": lists}),
+    )
+}
+
+/// All mailing lists with their number of confirmed subscribers, default first.
+pub async fn get_mailing_lists(pool: &PgPool) -> Result<Vec<MailingList>> {
+    let lists = sqlx::query_as(
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.description,
+            l.is_default,
+            COUNT(s.id) AS n_subscribers
+        FROM mailing_lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        LEFT JOIN subscriptions s ON s.id = m.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.is_default DESC, l.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(lists)
+}
+
+/// The list subscriptions and issues go to when none is picked.
+pub async fn default_list_id(pool: &PgPool) -> Result<Uuid> {
+    let row: (Uuid,) = sqlx::query_as(
+        r#"
+        SELECT list_id FROM mailing_lists WHERE is_default
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.0)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{default_list_id, get_mailing_lists, mailing_lists, MailingList};
+pub use post::create_mailing_list;
@@ -0,0 +1,43 @@
+use axum::{debug_handler, extract::State, response::Response, Form};
+use axum_messages::Messages;
+use uuid::Uuid;
+
+use crate::{controller::format, startup::AppState, Result};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[debug_handler]
+pub async fn create_mailing_list(
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<FormData>,
+) -> Result<Response> {
+    let name = params.name.trim();
+    if name.is_empty() {
+        messages.error("The list name can't be empty.");
+        return format::render().redirect("/admin/lists");
+    }
+    let result = sqlx::query(
+        r#"
+        INSERT INTO mailing_lists (list_id, name, description)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(name)
+    .bind(params.description.trim())
+    .execute(state.db_pool.as_ref())
+    .await?;
+    if result.rows_affected() == 0 {
+        messages.error(format!("A list named {} already exists.", name));
+    } else {
+        messages.info(format!("The list {} has been created.", name));
+    }
+    format::render().redirect("/admin/lists")
+}
@@ -1,9 +1,19 @@
 mod dashboard;
+mod email;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use dashboard::{admin_dashboard, get_username};
+pub use email::*;
+pub use lists::*;
 pub use logout::logout;
 pub use newsletter::*;
 pub use password::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
@@ -0,0 +1,210 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+    Extension, Form,
+};
+use axum_messages::Messages;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    controller::{admin::email::get_user_email, format},
+    domain::{ContentFormat, IssueContent, IssueTemplate, Recipient, SubscriberEmail},
+    errors::Error,
+    startup::AppState,
+    Result,
+};
+
+use super::get::render_newsletter_form;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    draft_id: Option<Uuid>,
+    title: String,
+    #[serde(default)]
+    content_format: ContentFormat,
+    content: String,
+    text_content: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Draft {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub content_format: String,
+    pub source_content: String,
+    pub source_text_content: Option<String>,
+}
+
+#[debug_handler]
+pub async fn save_draft(
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<DraftFormData>,
+) -> Result<Response> {
+    // Placeholders are only checked on publish, drafts may be unfinished.
+    let content =
+        match IssueContent::parse(params.content_format, params.content, params.text_content) {
+            Ok(content) => content,
+            Err(e) => {
+                messages.error(format!("The draft content is invalid: {}", e));
+                return format::render().redirect("/admin/newsletters");
+            }
+        };
+    let draft_id = match params.draft_id {
+        Some(draft_id) => {
+            let result = sqlx::query(
+                r#"
+                UPDATE newsletter_issues
+                SET
+                    title = $2,
+                    text_content = $3,
+                    html_content = $4,
+                    content_format = $5,
+                    source_content = $6,
+                    source_text_content = $7
+                WHERE newsletter_issue_id = $1 AND status = 'draft'
+                "#,
+            )
+            .bind(draft_id)
+            .bind(&params.title)
+            .bind(&content.text_content)
+            .bind(&content.html_content)
+            .bind(content.format.as_str())
+            .bind(&content.source_content)
+            .bind(&content.source_text_content)
+            .execute(state.db_pool.as_ref())
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+            draft_id
+        }
+        None => {
+            let draft_id = Uuid::new_v4();
+            sqlx::query(
+                r#"
+                INSERT INTO newsletter_issues (
+                    newsletter_issue_id,
+                    title,
+                    text_content,
+                    html_content,
+                    content_format,
+                    source_content,
+                    source_text_content,
+                    status
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, 'draft')
+                "#,
+            )
+            .bind(draft_id)
+            .bind(&params.title)
+            .bind(&content.text_content)
+            .bind(&content.html_content)
+            .bind(content.format.as_str())
+            .bind(&content.source_content)
+            .bind(&content.source_text_content)
+            .execute(state.db_pool.as_ref())
+            .await?;
+            draft_id
+        }
+    };
+    messages.info("The draft has been saved.");
+    format::render().redirect(&format!("/admin/newsletters/drafts/{}", draft_id))
+}
+
+#[debug_handler]
+pub async fn edit_draft_form(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(draft_id): Path<Uuid>,
+) -> Result<Response> {
+    let draft = get_draft(&state.db_pool, draft_id).await?;
+    render_newsletter_form(messages, &state, Some(draft)).await
+}
+
+#[debug_handler]
+pub async fn preview_draft(
+    State(state): State<AppState>,
+    Path(draft_id): Path<Uuid>,
+) -> Result<Response> {
+    let draft = get_draft(&state.db_pool, draft_id).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/newsletter_preview.html",
+        json!({"draft": draft}),
+    )
+}
+
+/// Mails the saved draft to the logged-in admin only; nothing is enqueued.
+#[debug_handler]
+pub async fn send_test_email(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(draft_id): Path<Uuid>,
+) -> Result<Response> {
+    let redirect_to = format!("/admin/newsletters/drafts/{}", draft_id);
+    let draft = get_draft(&state.db_pool, draft_id).await?;
+    let recipient = match get_user_email(user_id, &state.db_pool)
+        .await?
+        .map(SubscriberEmail::parse)
+    {
+        Some(Ok(recipient)) => recipient,
+        _ => {
+            messages.error("Set your email address before sending a test email.");
+            return format::render().redirect(&redirect_to);
+        }
+    };
+    let issue = match IssueTemplate::parse(&draft.title, &draft.html_content, &draft.text_content)
+        .and_then(|template| template.render(&Recipient::example(&state.base_url)))
+    {
+        Ok(issue) => issue,
+        Err(e) => {
+            messages.error(format!("The draft content is invalid: {}", e));
+            return format::render().redirect(&redirect_to);
+        }
+    };
+    let subject = format!("[TEST] {}", issue.title);
+    match state
+        .email_client
+        .send_email(
+            recipient,
+            &subject,
+            &issue.html_content,
+            &issue.text_content,
+            None,
+        )
+        .await
+    {
+        Ok(_) => messages.info("A test email has been sent to you."),
+        Err(e) => messages.error(format!("Failed to send the test email: {}", e)),
+    };
+    format::render().redirect(&redirect_to)
+}
+
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Draft> {
+    let draft: Option<Draft> = sqlx::query_as(
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            content_format,
+            source_content,
+            source_text_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+    )
+    .bind(draft_id)
+    .fetch_optional(pool)
+    .await?;
+    draft.ok_or(Error::NotFound)
+}
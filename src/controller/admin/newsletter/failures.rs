@@ -0,0 +1,49 @@
+use axum::{debug_handler, extract::State, response::Response};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{controller::format, startup::AppState, Result};
+
+#[debug_handler]
+pub async fn delivery_failures(State(state): State<AppState>) -> Result<Response> {
+    let failures = get_delivery_failures(&state.db_pool).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/delivery_failures.html",
+        json!({"failures": failures}),
+    )
+}
+
+#[derive(FromRow, Serialize)]
+struct DeliveryFailure {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+async fn get_delivery_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>> {
+    let failures = sqlx::query_as(
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_retries,
+            f.last_error,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY f.failed_at DESC
+        LIMIT 100
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(failures)
+}
@@ -3,21 +3,44 @@ use axum_messages::Messages;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::{controller::format, startup::AppState, Result};
+use crate::{
+    controller::{format, get_mailing_lists},
+    startup::AppState,
+    Result,
+};
+
+use super::{draft::Draft, issue::get_recent_issues};
 
 #[debug_handler]
 pub async fn publish_newsletter_form(
     messages: Messages,
     State(state): State<AppState>,
+) -> Result<Response> {
+    render_newsletter_form(messages, &state, None).await
+}
+
+/// Renders the publish form, pre-filled with `draft` when editing one.
+pub async fn render_newsletter_form(
+    messages: Messages,
+    state: &AppState,
+    draft: Option<Draft>,
 ) -> Result<Response> {
     let messages = messages
         .into_iter()
         .map(|msg| format!("{}", msg))
         .collect::<Vec<_>>();
     let idempotency_key = Uuid::new_v4();
+    let issues = get_recent_issues(&state.db_pool).await?;
+    let lists = get_mailing_lists(&state.db_pool).await?;
     format::render().view(
         &state.tera_engine,
         "admin/newsletter.html",
-        json!({"messages": messages, "idempotency_key":idempotency_key}),
+        json!({
+            "messages": messages,
+            "idempotency_key": idempotency_key,
+            "issues": issues,
+            "draft": draft,
+            "lists": lists,
+        }),
     )
 }
@@ -0,0 +1,150 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+};
+use axum_messages::Messages;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{controller::format, errors::Error, startup::AppState, Result};
+
+#[debug_handler]
+pub async fn newsletter_issue(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    let issue = get_issue_summary(&state.db_pool, newsletter_issue_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let stats = get_delivery_stats(&state.db_pool, newsletter_issue_id).await?;
+    let problems = get_delivery_problems(&state.db_pool, newsletter_issue_id).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/newsletter_issue.html",
+        json!({
+            "messages": messages,
+            "issue": issue,
+            "stats": stats,
+            "progress": stats.progress(),
+            "problems": problems,
+        }),
+    )
+}
+
+#[derive(FromRow, Serialize)]
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub scheduled_for: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+async fn get_issue_summary(pool: &PgPool, issue_id: Uuid) -> Result<Option<IssueSummary>> {
+    let issue = sqlx::query_as(
+        r#"
+        SELECT newsletter_issue_id, title, status, scheduled_for, published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+    )
+    .bind(issue_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(issue)
+}
+
+pub async fn get_recent_issues(pool: &PgPool) -> Result<Vec<IssueSummary>> {
+    let issues = sqlx::query_as(
+        r#"
+        SELECT newsletter_issue_id, title, status, scheduled_for, published_at
+        FROM newsletter_issues
+        ORDER BY COALESCE(published_at, scheduled_for) DESC
+        LIMIT 20
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(issues)
+}
+
+#[derive(FromRow, Serialize)]
+struct DeliveryStats {
+    pending: i64,
+    retrying: i64,
+    sent: i64,
+    failed: i64,
+    skipped_invalid_email: i64,
+}
+
+impl DeliveryStats {
+    /// Percentage of recipients whose delivery reached a final outcome.
+    fn progress(&self) -> i64 {
+        let processed = self.sent + self.failed + self.skipped_invalid_email;
+        let total = processed + self.pending;
+        if total == 0 {
+            100
+        } else {
+            processed * 100 / total
+        }
+    }
+}
+
+async fn get_delivery_stats(pool: &PgPool, issue_id: Uuid) -> Result<DeliveryStats> {
+    let stats = sqlx::query_as(
+        r#"
+        SELECT
+            (
+                SELECT count(*) FROM issue_delivery_queue WHERE newsletter_issue_id = $1
+            ) AS pending,
+            (
+                SELECT count(*) FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1 AND n_retries > 0
+            ) AS retrying,
+            count(*) FILTER (WHERE outcome = 'sent') AS sent,
+            count(*) FILTER (WHERE outcome = 'failed') AS failed,
+            count(*) FILTER (WHERE outcome = 'skipped_invalid_email') AS skipped_invalid_email
+        FROM issue_delivery_outcomes
+        WHERE newsletter_issue_id = $1
+        "#,
+    )
+    .bind(issue_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(stats)
+}
+
+#[derive(FromRow, Serialize)]
+struct DeliveryProblem {
+    subscriber_email: String,
+    outcome: String,
+    last_error: Option<String>,
+    recorded_at: DateTime<Utc>,
+}
+
+async fn get_delivery_problems(pool: &PgPool, issue_id: Uuid) -> Result<Vec<DeliveryProblem>> {
+    let problems = sqlx::query_as(
+        r#"
+        SELECT o.subscriber_email, o.outcome, f.last_error, o.recorded_at
+        FROM issue_delivery_outcomes o
+        LEFT JOIN issue_delivery_failures f
+            USING (newsletter_issue_id, subscriber_email)
+        WHERE o.newsletter_issue_id = $1 AND o.outcome <> 'sent'
+        ORDER BY o.recorded_at DESC
+        LIMIT 100
+        "#,
+    )
+    .bind(issue_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(problems)
+}
@@ -1,4 +1,12 @@
+mod draft;
+mod failures;
 mod get;
+mod issue;
 mod post;
+mod schedule;
+pub use draft::{edit_draft_form, preview_draft, save_draft, send_test_email};
+pub use failures::delivery_failures;
 pub use get::publish_newsletter_form;
+pub use issue::newsletter_issue;
 pub use post::publish_newsletter;
+pub use schedule::{cancel_newsletter_issue, reschedule_newsletter_issue};
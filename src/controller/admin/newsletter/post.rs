@@ -1,22 +1,34 @@
-use axum::{debug_handler, extract::State, response::Response, Extension, Form};
+use axum::{debug_handler, extract::State, response::Response, Extension};
+use axum_extra::extract::Form;
 use axum_messages::Messages;
-use sqlx::{Executor, Postgres, Transaction};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    controller::format,
+    controller::{default_list_id, format},
+    domain::{ContentFormat, IssueContent, IssueTemplate},
     errors::Error,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::enqueue_delivery_tasks,
     startup::AppState,
     Result,
 };
 
+use super::schedule::parse_send_at;
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
-    text_content: String,
-    html_content: String,
+    #[serde(default)]
+    content_format: ContentFormat,
+    content: String,
+    text_content: Option<String>,
     idempotency_key: String,
+    send_at: Option<String>,
+    draft_id: Option<Uuid>,
+    #[serde(default)]
+    list_id: Vec<Uuid>,
 }
 
 #[debug_handler]
@@ -26,13 +38,40 @@ pub async fn publish_newsletter(
     State(state): State<AppState>,
     Form(params): Form<FormData>,
 ) -> Result<Response> {
+    let scheduled_for = match parse_send_at(params.send_at.as_deref().unwrap_or_default()) {
+        Ok(scheduled_for) => scheduled_for,
+        Err(e) => {
+            messages.error(e.to_string());
+            return format::render().redirect("/admin/newsletters");
+        }
+    };
+    let content = match parse_issue_content(
+        &params.title,
+        params.content_format,
+        params.content,
+        params.text_content,
+        &state.base_url,
+    ) {
+        Ok(content) => content,
+        Err(e) => {
+            messages.error(format!("The issue content is invalid: {}", e));
+            return format::render().redirect("/admin/newsletters");
+        }
+    };
+    let mut list_ids = params.list_id;
+    list_ids.sort();
+    list_ids.dedup();
+    if !lists_exist(&state.db_pool, &list_ids).await? {
+        messages.error("Unknown mailing list.");
+        return format::render().redirect("/admin/newsletters");
+    }
     let idempotency_key: IdempotencyKey = params
         .idempotency_key
         .try_into()
         .map_err(|_| Error::InvalidIdempotencyKey)?;
 
     let mut transaction = match try_processing(&state.db_pool, &idempotency_key, user_id).await? {
-        NextAction::StartProcessing(t) => t,
+        NextAction::StartProcessing(t) => *t,
         NextAction::ReturnSavedResponse(response) => {
             messages.info("The newsletter issue has been published!");
             return Ok(response);
@@ -40,27 +79,63 @@ pub async fn publish_newsletter(
     };
     let issue_id = insert_newsletter_issue(
         &mut transaction,
+        params.draft_id,
         &params.title,
-        &params.text_content,
-        &params.html_content,
+        &content,
+        scheduled_for,
     )
     .await?;
+    if list_ids.is_empty() {
+        list_ids.push(default_list_id(&state.db_pool).await?);
+    }
+    target_lists(&mut transaction, issue_id, &list_ids).await?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    // Scheduled issues are enqueued by the background worker once they are due.
+    if scheduled_for.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    }
 
     let response = format::render().redirect("/admin/newsletters")?;
     let response = save_response(transaction, &idempotency_key, user_id, response).await?;
-    messages.info("The newsletter issue has been published!");
+    match scheduled_for {
+        Some(scheduled_for) => messages.info(format!(
+            "The newsletter issue has been scheduled for {}!",
+            scheduled_for
+        )),
+        None => messages.info("The newsletter issue has been published!"),
+    };
     Ok(response)
 }
 
+/// Processes the submitted content and checks its placeholders, so a broken
+/// issue is rejected now rather than once per recipient.
+pub fn parse_issue_content(
+    title: &str,
+    content_format: ContentFormat,
+    content: String,
+    text_content: Option<String>,
+    base_url: &str,
+) -> Result<IssueContent> {
+    let content = IssueContent::parse(content_format, content, text_content)?;
+    IssueTemplate::validate(
+        title,
+        &content.html_content,
+        &content.text_content,
+        base_url,
+    )?;
+    Ok(content)
+}
+
+/// Inserts a new issue, or publishes the draft identified by `draft_id`
+/// with the submitted content.
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
+    draft_id: Option<Uuid>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
+    scheduled_for: Option<DateTime<Utc>>,
 ) -> Result<Uuid> {
-    let newsletter_issue_id = Uuid::new_v4();
+    let newsletter_issue_id = draft_id.unwrap_or_else(Uuid::new_v4);
     let query = sqlx::query(
         r#"
         INSERT INTO newsletter_issues (
@@ -68,36 +143,79 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
+            content_format,
+            source_content,
+            source_text_content,
+            status,
+            scheduled_for,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, CASE WHEN $8 = 'published' THEN now() END)
+        ON CONFLICT (newsletter_issue_id) DO UPDATE
+        SET
+            title = EXCLUDED.title,
+            text_content = EXCLUDED.text_content,
+            html_content = EXCLUDED.html_content,
+            content_format = EXCLUDED.content_format,
+            source_content = EXCLUDED.source_content,
+            source_text_content = EXCLUDED.source_text_content,
+            status = EXCLUDED.status,
+            scheduled_for = EXCLUDED.scheduled_for,
+            published_at = EXCLUDED.published_at
+        WHERE newsletter_issues.status = 'draft'
         "#,
     )
     .bind(newsletter_issue_id)
     .bind(title)
-    .bind(text_content)
-    .bind(html_content);
-    transaction.execute(query).await?;
+    .bind(&content.text_content)
+    .bind(&content.html_content)
+    .bind(content.format.as_str())
+    .bind(&content.source_content)
+    .bind(&content.source_text_content)
+    .bind(if scheduled_for.is_some() {
+        "scheduled"
+    } else {
+        "published"
+    })
+    .bind(scheduled_for);
+    let result = transaction.execute(query).await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
     Ok(newsletter_issue_id)
 }
 
-async fn enqueue_delivery_tasks(
+/// Whether every one of the distinct `list_ids` exists.
+async fn lists_exist(pool: &PgPool, list_ids: &[Uuid]) -> Result<bool> {
+    let (n_lists,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT count(*) FROM mailing_lists WHERE list_id = ANY($1)
+        "#,
+    )
+    .bind(list_ids)
+    .fetch_one(pool)
+    .await?;
+    Ok(n_lists as usize == list_ids.len())
+}
+
+/// Records the lists an issue goes to, which `enqueue_delivery_tasks` reads.
+/// The `list_ids` must be distinct.
+async fn target_lists(
     transaction: &mut Transaction<'static, Postgres>,
     newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
 ) -> Result<()> {
     let query = sqlx::query(
         r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id FROM mailing_lists WHERE list_id = ANY($2)
         "#,
     )
-    .bind(newsletter_issue_id);
-
-    transaction.execute(query).await?;
+    .bind(newsletter_issue_id)
+    .bind(list_ids);
+    let result = transaction.execute(query).await?;
+    if result.rows_affected() as usize != list_ids.len() {
+        return Err(Error::BadRequest("Unknown mailing list.".into()));
+    }
     Ok(())
 }
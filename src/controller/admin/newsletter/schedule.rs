@@ -0,0 +1,100 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+    Form,
+};
+use axum_messages::Messages;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use crate::{controller::format, errors::Error, startup::AppState, Result};
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    send_at: String,
+}
+
+/// Parses the value of a `datetime-local` input, interpreted as UTC.
+/// An empty value means "send now".
+pub fn parse_send_at(send_at: &str) -> Result<Option<DateTime<Utc>>> {
+    let send_at = send_at.trim();
+    if send_at.is_empty() {
+        return Ok(None);
+    }
+    let scheduled_for = NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| Error::BadRequest(format!("{} is not a valid send-at time.", send_at)))?
+        .and_utc();
+    if scheduled_for <= Utc::now() {
+        return Err(Error::BadRequest(
+            "The send-at time must be in the future.".to_string(),
+        ));
+    }
+    Ok(Some(scheduled_for))
+}
+
+#[debug_handler]
+pub async fn cancel_newsletter_issue(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+) -> Result<Response> {
+    let query = sqlx::query(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+    )
+    .bind(newsletter_issue_id);
+    // Guarded by `status = 'scheduled'` so it cannot race with the worker
+    // publishing the issue.
+    let result = query.execute(state.db_pool.as_ref()).await?;
+    if result.rows_affected() == 1 {
+        messages.info("The scheduled newsletter issue has been cancelled.");
+    } else {
+        messages.error("Only scheduled issues can be cancelled.");
+    }
+    format::render().redirect(&format!("/admin/newsletters/{}", newsletter_issue_id))
+}
+
+#[debug_handler]
+pub async fn reschedule_newsletter_issue(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    Form(params): Form<RescheduleFormData>,
+) -> Result<Response> {
+    let redirect_to = format!("/admin/newsletters/{}", newsletter_issue_id);
+    let scheduled_for = match parse_send_at(&params.send_at) {
+        Ok(Some(scheduled_for)) => scheduled_for,
+        Ok(None) => {
+            messages.error("Please pick a new send-at time.");
+            return format::render().redirect(&redirect_to);
+        }
+        Err(e) => {
+            messages.error(e.to_string());
+            return format::render().redirect(&redirect_to);
+        }
+    };
+    let query = sqlx::query(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+    )
+    .bind(newsletter_issue_id)
+    .bind(scheduled_for);
+    let result = query.execute(state.db_pool.as_ref()).await?;
+    if result.rows_affected() == 1 {
+        messages.info(format!(
+            "The newsletter issue has been rescheduled for {}.",
+            scheduled_for
+        ));
+    } else {
+        messages.error("Only scheduled issues can be rescheduled.");
+    }
+    format::render().redirect(&redirect_to)
+}
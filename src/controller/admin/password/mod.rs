@@ -2,4 +2,4 @@ mod get;
 mod post;
 
 pub use get::change_password_form;
-pub use post::change_password;
+pub use post::{change_password, send_password_changed_email};
@@ -3,11 +3,19 @@ use axum_messages::Messages;
 use axum_session::Session;
 use axum_session_redispool::SessionRedisPool;
 use secrecy::ExposeSecret;
+use serde_json::json;
+use uuid::Uuid;
 
 use crate::{
-    authentication::{change_password_store, validate_credentials, Credentials},
-    controller::{admin::dashboard::get_username, format},
-    domain::ChangePasswordForm,
+    authentication::{
+        change_password_store, hash_password, password_policy_violation, validate_credentials,
+        Credentials,
+    },
+    controller::{
+        admin::{dashboard::get_username, email::get_user_email},
+        format,
+    },
+    domain::{ChangePasswordForm, SubscriberEmail},
     startup::AppState,
     Result,
 };
@@ -31,13 +39,60 @@ pub async fn change_password(
     };
     let username = get_username(user_id, &state.db_pool).await?;
     let credentials = Credentials {
-        username,
+        username: username.clone(),
         password: params.current_password,
     };
     if let Err(e) = validate_credentials(credentials, &state.db_pool).await {
         messages.error(e.to_string());
         return format::render().redirect("/admin/password");
     };
-    change_password_store(user_id, params.new_password, &state.db_pool).await?;
+    let email = get_user_email(user_id, &state.db_pool).await?;
+    let user_inputs = [Some(username.as_str()), email.as_deref()];
+    if let Some(reason) = password_policy_violation(
+        &state.password_policy,
+        &params.new_password,
+        &user_inputs.into_iter().flatten().collect::<Vec<_>>(),
+    )
+    .await?
+    {
+        messages.error(reason);
+        return format::render().redirect("/admin/password");
+    }
+    let password_hash = hash_password(params.new_password).await?;
+    let mut transaction = state.db_pool.begin().await?;
+    change_password_store(&mut transaction, user_id, password_hash).await?;
+    transaction.commit().await?;
+    if let Err(e) = send_password_changed_email(&state, user_id, &username).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send the password changed notice",
+        );
+    }
     format::render().redirect("/admin/dashboard")
 }
+
+/// Lets the admin know their password changed, if they have set an email.
+pub async fn send_password_changed_email(
+    state: &AppState,
+    user_id: Uuid,
+    username: &str,
+) -> Result<()> {
+    let Some(email) = get_user_email(user_id, &state.db_pool).await? else {
+        return Ok(());
+    };
+    let body = state
+        .tera_engine
+        .render_email("password_changed", json!({"username": username}))?;
+    state
+        .email_client
+        .send_email(
+            SubscriberEmail::parse(email)?,
+            "Your password was changed",
+            &body.html,
+            &body.text,
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::{controller::format, errors::Error, startup::AppState, Result};
+
+#[derive(FromRow, Serialize)]
+struct ConsentEvent {
+    email: String,
+    event_type: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+/// The signups and confirmations recorded for a subscriber, as evidence of
+/// their consent.
+#[debug_handler]
+pub async fn subscriber_consent(
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response> {
+    let (email, status): (String, String) = sqlx::query_as(
+        r#"
+        SELECT email, status FROM subscriptions WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(state.db_pool.as_ref())
+    .await?
+    .ok_or(Error::NotFound)?;
+    let events: Vec<ConsentEvent> = sqlx::query_as(
+        r#"
+        SELECT email, event_type, source, ip_address, user_agent, request_id, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_all(state.db_pool.as_ref())
+    .await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/subscriber_consent.html",
+        json!({"email": email, "status": status, "events": events}),
+    )
+}
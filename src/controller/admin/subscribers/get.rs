@@ -0,0 +1,229 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    response::Response,
+};
+use axum_messages::Messages;
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgArguments, prelude::FromRow, query::QueryAs, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::{controller::format, errors::Error, startup::AppState, Result};
+
+const PAGE_SIZE: i64 = 50;
+
+/// Matches the subscriptions selected by a [`SubscriberFilter`], whose values
+/// [`SubscriberFilter::bind`] binds as `$1` to `$4`.
+pub const FILTER_CONDITION: &str = r#"
+    ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+    AND ($2::text IS NULL OR status = $2)
+    AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+    AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+"#;
+const STATUSES: &[&str] = &[
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "suppressed",
+];
+
+/// The query string of the subscribers page. Empty fields, as sent by the
+/// filter form, mean "any".
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct SubscriberFilter {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    status: String,
+    /// Signed up on or after this day, as `YYYY-MM-DD`.
+    #[serde(default)]
+    from: String,
+    /// Signed up on or before this day, as `YYYY-MM-DD`.
+    #[serde(default)]
+    to: String,
+    #[serde(default = "first_page")]
+    page: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+impl SubscriberFilter {
+    pub fn validate(&self) -> Result<()> {
+        self.status()?;
+        self.signed_up_from()?;
+        self.signed_up_before()?;
+        Ok(())
+    }
+
+    pub fn bind<'q, O>(
+        &self,
+        query: QueryAs<'q, Postgres, O, PgArguments>,
+    ) -> Result<QueryAs<'q, Postgres, O, PgArguments>> {
+        Ok(query
+            .bind(self.search_pattern())
+            .bind(self.status()?.map(str::to_string))
+            .bind(self.signed_up_from()?)
+            .bind(self.signed_up_before()?))
+    }
+
+    /// `ILIKE` pattern matching emails and names containing `q`.
+    fn search_pattern(&self) -> Option<String> {
+        let q = self.q.trim();
+        if q.is_empty() {
+            return None;
+        }
+        let escaped = q
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Some(format!("%{}%", escaped))
+    }
+
+    fn status(&self) -> Result<Option<&str>> {
+        match self.status.trim() {
+            "" => Ok(None),
+            status if STATUSES.contains(&status) => Ok(Some(status)),
+            status => Err(Error::BadRequest(format!(
+                "{} is not a valid status.",
+                status
+            ))),
+        }
+    }
+
+    fn signed_up_from(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(parse_day(&self.from)?.map(start_of_day))
+    }
+
+    /// The `to` day is inclusive, so the range ends when the next day starts.
+    fn signed_up_before(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(parse_day(&self.to)?
+            .and_then(|day| day.checked_add_days(Days::new(1)))
+            .map(start_of_day))
+    }
+
+    fn page_link(&self, page: i64) -> Result<String> {
+        self.link("/admin/subscribers", page)
+    }
+
+    /// The CSV export of every page of the current search.
+    fn export_link(&self) -> Result<String> {
+        self.link("/admin/subscribers/export", first_page())
+    }
+
+    fn link(&self, path: &str, page: i64) -> Result<String> {
+        let filter = Self {
+            page,
+            ..self.clone()
+        };
+        Ok(format!(
+            "{}?{}",
+            path,
+            serde_urlencoded::to_string(filter)
+                .map_err(|e| Error::Message(format!("Failed to encode the filter: {}", e)))?
+        ))
+    }
+}
+
+fn start_of_day(day: NaiveDate) -> DateTime<Utc> {
+    day.and_time(NaiveTime::MIN).and_utc()
+}
+
+fn parse_day(day: &str) -> Result<Option<NaiveDate>> {
+    let day = day.trim();
+    if day.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| Error::BadRequest(format!("{} is not a valid date.", day)))
+}
+
+#[derive(FromRow, Serialize)]
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[debug_handler]
+pub async fn subscribers(
+    messages: Messages,
+    State(state): State<AppState>,
+    Query(filter): Query<SubscriberFilter>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    let page = filter.page.max(1);
+    let (subscribers, n_subscribers) = search_subscribers(&state.db_pool, &filter, page).await?;
+    let n_pages = ((n_subscribers + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let previous_page = if page > 1 {
+        Some(filter.page_link(page - 1)?)
+    } else {
+        None
+    };
+    let next_page = if page < n_pages {
+        Some(filter.page_link(page + 1)?)
+    } else {
+        None
+    };
+    format::render().view(
+        &state.tera_engine,
+        "admin/subscribers.html",
+        json!({
+            "messages": messages,
+            "subscribers": subscribers,
+            "n_subscribers": n_subscribers,
+            "filter": filter,
+            "statuses": STATUSES,
+            "page": page,
+            "n_pages": n_pages,
+            "previous_page": previous_page,
+            "next_page": next_page,
+            "export_link": filter.export_link()?,
+        }),
+    )
+}
+
+/// One page of the subscribers matching `filter`, newest first, along with
+/// how many match in total.
+async fn search_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    page: i64,
+) -> Result<(Vec<Subscriber>, i64)> {
+    let sql = format!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE {}
+        ORDER BY subscribed_at DESC, email
+        LIMIT $5 OFFSET $6
+        "#,
+        FILTER_CONDITION
+    );
+    let offset = (page - 1)
+        .checked_mul(PAGE_SIZE)
+        .ok_or_else(|| Error::BadRequest(format!("Page {} does not exist.", page)))?;
+    let subscribers = filter
+        .bind(sqlx::query_as(&sql))?
+        .bind(PAGE_SIZE)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+    let sql = format!(
+        r#"
+        SELECT COUNT(*) FROM subscriptions WHERE {}
+        "#,
+        FILTER_CONDITION
+    );
+    let (n_subscribers,) = filter.bind(sqlx::query_as(&sql))?.fetch_one(pool).await?;
+    Ok((subscribers, n_subscribers))
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use axum::{
+    debug_handler,
+    extract::{Multipart, State},
+    response::Response,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Executor, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    controller::{
+        default_list_id, format, generate_subscription_token, get_mailing_lists, list_exists,
+        record_consent, send_confirm_email, ConsentEventType, ConsentMetadata,
+    },
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    errors::Error,
+    startup::AppState,
+    Result,
+};
+
+/// The consent source of imported subscribers; the evidence is the admin's
+/// request.
+const IMPORT_SOURCE: &str = "csv_import";
+/// Confirmation emails of an import in flight at once.
+const CONFIRMATION_EMAIL_CONCURRENCY: usize = 10;
+
+/// A row of the uploaded file that wasn't imported, and why.
+#[derive(Serialize)]
+struct LineError {
+    line: u64,
+    message: String,
+}
+
+/// A valid row of the uploaded file.
+struct ImportRow {
+    line: u64,
+    subscriber: NewSubscriber,
+}
+
+#[derive(Serialize)]
+struct ImportReport {
+    n_imported: usize,
+    n_already_registered: usize,
+    n_confirmations_queued: usize,
+    errors: Vec<LineError>,
+}
+
+/// What the upload form sends: a CSV file with `email` and `name` columns,
+/// whether its addresses are already confirmed, and the list they join.
+#[derive(Default)]
+struct ImportForm {
+    file: Vec<u8>,
+    confirmed: bool,
+    list_id: Option<Uuid>,
+}
+
+impl ImportForm {
+    async fn parse(mut multipart: Multipart) -> Result<Self> {
+        let mut form = Self::default();
+        while let Some(field) = multipart.next_field().await? {
+            match field.name() {
+                Some("file") => form.file = field.bytes().await?.to_vec(),
+                Some("confirmed") => form.confirmed = field.text().await? == "true",
+                Some("list_id") => {
+                    let list_id = field.text().await?;
+                    if !list_id.is_empty() {
+                        form.list_id = Some(list_id.parse().map_err(|_| {
+                            Error::BadRequest(format!("{} is not a valid list id.", list_id))
+                        })?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(form)
+    }
+}
+
+#[debug_handler]
+pub async fn import_subscribers_form(State(state): State<AppState>) -> Result<Response> {
+    render_import(&state, None).await
+}
+
+/// Imports the valid rows of the file and reports the others by line, so a
+/// fixed file can be uploaded again without creating duplicates.
+#[debug_handler]
+pub async fn import_subscribers(
+    State(state): State<AppState>,
+    metadata: ConsentMetadata,
+    multipart: Multipart,
+) -> Result<Response> {
+    let form = ImportForm::parse(multipart).await?;
+    let list_id = match form.list_id {
+        Some(list_id) if list_exists(&state.db_pool, list_id).await? => list_id,
+        Some(_) => return Err(Error::BadRequest("Unknown mailing list.".into())),
+        None => default_list_id(&state.db_pool).await?,
+    };
+    let (rows, mut errors) = parse_csv(&form.file)?;
+
+    let mut transaction = state.db_pool.begin().await?;
+    let inserted = insert_subscribers(&mut transaction, &rows, form.confirmed).await?;
+    let query = sqlx::query(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id)
+        SELECT subscriber_id, $2 FROM UNNEST($1::uuid[]) AS t(subscriber_id)
+        "#,
+    )
+    .bind(inserted.values().collect::<Vec<_>>())
+    .bind(list_id);
+    transaction.execute(query).await?;
+    let subscriber_ids = inserted.values().copied().collect::<Vec<_>>();
+    record_consent(
+        &mut transaction,
+        &subscriber_ids,
+        ConsentEventType::Signup,
+        IMPORT_SOURCE,
+        &metadata,
+    )
+    .await?;
+    if form.confirmed {
+        record_consent(
+            &mut transaction,
+            &subscriber_ids,
+            ConsentEventType::Confirmation,
+            IMPORT_SOURCE,
+            &metadata,
+        )
+        .await?;
+    }
+
+    let mut tokens = Vec::new();
+    if !form.confirmed {
+        for (email, subscriber_id) in &inserted {
+            tokens.push((email.clone(), *subscriber_id, generate_subscription_token()));
+        }
+        let query = sqlx::query(
+            r#"
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+            SELECT * FROM UNNEST($1::text[], $2::uuid[])
+            "#,
+        )
+        .bind(tokens.iter().map(|t| t.2.as_str()).collect::<Vec<_>>())
+        .bind(tokens.iter().map(|t| t.1).collect::<Vec<_>>());
+        transaction.execute(query).await?;
+    }
+    transaction.commit().await?;
+
+    let n_already_registered = rows.len() - inserted.len();
+    let mut lines = rows
+        .into_iter()
+        .map(|row| (row.subscriber.email.as_ref().to_string(), row))
+        .collect::<HashMap<_, _>>();
+    let confirmations = tokens
+        .into_iter()
+        .filter_map(|(email, _, subscription_token)| {
+            Some((lines.remove(&email)?, subscription_token))
+        })
+        .collect::<Vec<_>>();
+    let n_confirmations_queued = confirmations.len();
+    send_confirm_emails_in_background(&state, confirmations);
+    errors.sort_by_key(|e| e.line);
+    let report = ImportReport {
+        n_imported: inserted.len(),
+        n_already_registered,
+        n_confirmations_queued,
+        errors,
+    };
+    render_import(&state, Some(report)).await
+}
+
+/// Sends the confirmation emails of an import after the response, as a large
+/// file would otherwise keep the request waiting on thousands of sends. The
+/// subscribers are saved either way; a failed email can be resent by
+/// subscribing again.
+fn send_confirm_emails_in_background(state: &AppState, confirmations: Vec<(ImportRow, String)>) {
+    if confirmations.is_empty() {
+        return;
+    }
+    let state = state.clone();
+    let task = async move {
+        futures_util::stream::iter(confirmations)
+            .for_each_concurrent(
+                CONFIRMATION_EMAIL_CONCURRENCY,
+                |(ImportRow { line, subscriber }, subscription_token)| {
+                    let state = &state;
+                    async move {
+                        if let Err(e) = send_confirm_email(
+                            &state.email_client,
+                            &state.tera_engine,
+                            subscriber,
+                            &state.base_url,
+                            &subscription_token,
+                        )
+                        .await
+                        {
+                            tracing::error!(
+                                error.cause_chain = ?e,
+                                error.message = %e,
+                                line,
+                                "Failed to send the confirmation email of an imported subscriber",
+                            );
+                        }
+                    }
+                },
+            )
+            .await;
+    };
+    tokio::spawn(task.instrument(tracing::Span::current()));
+}
+
+async fn render_import(state: &AppState, report: Option<ImportReport>) -> Result<Response> {
+    let lists = get_mailing_lists(&state.db_pool).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/subscribers_import.html",
+        json!({"lists": lists, "report": report}),
+    )
+}
+
+/// Validates every row, returning the subscribers to import with their line
+/// numbers and the errors of the rows that can't be.
+fn parse_csv(file: &[u8]) -> Result<(Vec<ImportRow>, Vec<LineError>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(file);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::BadRequest(format!("The file has no {} column.", name)))
+    };
+    let email_column = column("email")?;
+    let name_column = column("name")?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut first_lines = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(LineError {
+                    line: e.position().map_or(0, |p| p.line()),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let field = |column| record.get(column).unwrap_or_default().to_string();
+        let subscriber = SubscriberEmail::parse(field(email_column)).and_then(|email| {
+            Ok(NewSubscriber {
+                email,
+                name: SubscriberName::parse(field(name_column))?,
+            })
+        });
+        match subscriber {
+            Ok(subscriber) => match first_lines.get(subscriber.email.as_ref()) {
+                Some(first_line) => errors.push(LineError {
+                    line,
+                    message: format!("Duplicate of line {}.", first_line),
+                }),
+                None => {
+                    first_lines.insert(subscriber.email.as_ref().to_string(), line);
+                    rows.push(ImportRow { line, subscriber });
+                }
+            },
+            Err(e) => errors.push(LineError {
+                line,
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok((rows, errors))
+}
+
+/// Inserts the subscribers in one statement, skipping registered addresses,
+/// and returns the ids of the new ones by email.
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    rows: &[ImportRow],
+    confirmed: bool,
+) -> Result<HashMap<String, Uuid>> {
+    let status = if confirmed {
+        "confirmed"
+    } else {
+        "pending_confirmation"
+    };
+    let inserted: Vec<(String, Uuid)> = sqlx::query_as(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT id, email, name, now(), $4
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[]) AS t(id, email, name)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING email, id
+        "#,
+    )
+    .bind(rows.iter().map(|_| Uuid::new_v4()).collect::<Vec<_>>())
+    .bind(
+        rows.iter()
+            .map(|row| row.subscriber.email.as_ref())
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        rows.iter()
+            .map(|row| row.subscriber.name.as_ref())
+            .collect::<Vec<_>>(),
+    )
+    .bind(status)
+    .fetch_all(&mut **transaction)
+    .await?;
+    Ok(inserted.into_iter().collect())
+}
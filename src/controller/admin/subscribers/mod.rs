@@ -0,0 +1,11 @@
+mod consent;
+mod export;
+mod get;
+mod import;
+mod post;
+
+pub use consent::subscriber_consent;
+pub use export::export_subscribers;
+pub use get::subscribers;
+pub use import::{import_subscribers, import_subscribers_form};
+pub use post::{confirm_subscriber_manually, delete_subscriber, suppress_subscriber};
@@ -0,0 +1,124 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+};
+use axum_messages::Messages;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    controller::{confirm_subscriber, format, record_consent, ConsentEventType, ConsentMetadata},
+    errors::Error,
+    startup::AppState,
+    Result,
+};
+
+/// Confirms a pending subscriber on their behalf, e.g. when they can't receive
+/// the confirmation email. Unsubscribed and suppressed subscribers are left
+/// alone.
+#[debug_handler]
+pub async fn confirm_subscriber_manually(
+    messages: Messages,
+    State(state): State<AppState>,
+    metadata: ConsentMetadata,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response> {
+    let mut transaction = state.db_pool.begin().await?;
+    let email = get_email(&mut transaction, subscriber_id).await?;
+    if !confirm_subscriber(&mut transaction, subscriber_id).await? {
+        messages.error(format!("{} is not pending confirmation.", email));
+        return format::render().redirect("/admin/subscribers");
+    }
+    record_consent(
+        &mut transaction,
+        &[subscriber_id],
+        ConsentEventType::AdminOverride,
+        "admin",
+        &metadata,
+    )
+    .await?;
+    transaction.commit().await?;
+    messages.info(format!("{} has been confirmed.", email));
+    format::render().redirect("/admin/subscribers")
+}
+
+/// Stops all email to the subscriber, like a hard bounce would.
+#[debug_handler]
+pub async fn suppress_subscriber(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response> {
+    let mut transaction = state.db_pool.begin().await?;
+    let email = get_email(&mut transaction, subscriber_id).await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE subscriptions SET status = 'suppressed' WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    dequeue_deliveries(&mut transaction, &email).await?;
+    transaction.commit().await?;
+    messages.info(format!("{} has been suppressed.", email));
+    format::render().redirect("/admin/subscribers")
+}
+
+#[debug_handler]
+pub async fn delete_subscriber(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response> {
+    let mut transaction = state.db_pool.begin().await?;
+    let email = get_email(&mut transaction, subscriber_id).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM subscription_tokens WHERE subscriber_id = $1
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM subscriptions WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    dequeue_deliveries(&mut transaction, &email).await?;
+    transaction.commit().await?;
+    messages.info(format!("{} has been deleted.", email));
+    format::render().redirect("/admin/subscribers")
+}
+
+/// Locks the subscriber so concurrent actions on them are serialised.
+async fn get_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<String> {
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"
+        SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(&mut **transaction)
+    .await?;
+    row.map(|r| r.0).ok_or(Error::NotFound)
+}
+
+async fn dequeue_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        DELETE FROM issue_delivery_queue WHERE subscriber_email = $1
+        "#,
+    )
+    .bind(email);
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use axum::{debug_handler, extract::State, response::Response, Extension};
+use axum_messages::Messages;
+use axum_session::Session;
+use axum_session_redispool::SessionRedisPool;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    authentication::get_totp_secret,
+    controller::{admin::dashboard::get_username, format},
+    domain::TotpSecret,
+    startup::AppState,
+    Result,
+};
+
+/// The issuer authenticator apps list the account under.
+pub const TOTP_ISSUER: &str = "zero2prod";
+
+/// Shows whether two-factor authentication is on, or the secret to enroll an
+/// authenticator app with. The secret is only kept in the session until the
+/// user proves they have set it up.
+#[debug_handler]
+pub async fn two_factor_settings(
+    Extension(user_id): Extension<Uuid>,
+    session: Session<SessionRedisPool>,
+    messages: Messages,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    if get_totp_secret(user_id, &state.db_pool).await?.is_some() {
+        return format::render().view(
+            &state.tera_engine,
+            "admin/two_factor.html",
+            json!({"messages": messages, "enabled": true}),
+        );
+    }
+    let secret = match session
+        .get::<String>("totp_pending_secret")
+        .and_then(|secret| TotpSecret::parse_base32(&secret).ok())
+    {
+        Some(secret) => secret,
+        None => {
+            let secret = TotpSecret::generate();
+            session.set("totp_pending_secret", secret.base32());
+            secret
+        }
+    };
+    let username = get_username(user_id, &state.db_pool).await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/two_factor.html",
+        json!({
+            "messages": messages,
+            "enabled": false,
+            "secret": secret.base32(),
+            "otpauth_uri": secret.otpauth_uri(TOTP_ISSUER, &username),
+        }),
+    )
+}
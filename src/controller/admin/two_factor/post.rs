@@ -0,0 +1,95 @@
+use axum::{debug_handler, extract::State, response::Response, Extension, Form};
+use axum_messages::Messages;
+use axum_session::Session;
+use axum_session_redispool::SessionRedisPool;
+use chrono::Utc;
+use secrecy::Secret;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{
+        disable_two_factor, enable_two_factor, validate_credentials, verify_second_factor,
+        Credentials,
+    },
+    controller::{admin::dashboard::get_username, format},
+    domain::TotpSecret,
+    startup::AppState,
+    Result,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+    current_password: Secret<String>,
+}
+
+/// Turns two-factor authentication on once the user enters their password
+/// and a code from the app they enrolled, then shows their recovery codes,
+/// only this once.
+#[debug_handler]
+pub async fn enable_two_factor_authentication(
+    Extension(user_id): Extension<Uuid>,
+    session: Session<SessionRedisPool>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<FormData>,
+) -> Result<Response> {
+    let Some(secret) = session
+        .get::<String>("totp_pending_secret")
+        .and_then(|secret| TotpSecret::parse_base32(&secret).ok())
+    else {
+        return format::render().redirect("/admin/two-factor");
+    };
+    if let Err(e) = check_password(&state, user_id, params.current_password).await {
+        messages.error(e.to_string());
+        return format::render().redirect("/admin/two-factor");
+    }
+    let Some(step) = secret.verify(&params.code, Utc::now(), None) else {
+        messages.error("The code is invalid, check the clock of your device and try again.");
+        return format::render().redirect("/admin/two-factor");
+    };
+    let recovery_codes = enable_two_factor(user_id, &secret, step, &state.db_pool).await?;
+    session.remove("totp_pending_secret");
+    format::render().view(
+        &state.tera_engine,
+        "admin/two_factor.html",
+        json!({
+            "messages": ["Two-factor authentication is now on."],
+            "enabled": true,
+            "recovery_codes": recovery_codes,
+        }),
+    )
+}
+
+/// Turns two-factor authentication off, given both the password and a code.
+#[debug_handler]
+pub async fn disable_two_factor_authentication(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<FormData>,
+) -> Result<Response> {
+    if let Err(e) = check_password(&state, user_id, params.current_password).await {
+        messages.error(e.to_string());
+        return format::render().redirect("/admin/two-factor");
+    }
+    if !verify_second_factor(user_id, &params.code, &state.db_pool).await? {
+        messages.error("The code is invalid or has already been used.");
+        return format::render().redirect("/admin/two-factor");
+    }
+    disable_two_factor(user_id, &state.db_pool).await?;
+    messages.info("Two-factor authentication is now off.");
+    format::render().redirect("/admin/two-factor")
+}
+
+/// A session alone isn't enough to change how the user logs in, in case it
+/// was stolen.
+async fn check_password(state: &AppState, user_id: Uuid, password: Secret<String>) -> Result<()> {
+    let credentials = Credentials {
+        username: get_username(user_id, &state.db_pool).await?,
+        password,
+    };
+    validate_credentials(credentials, &state.db_pool).await?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use axum::{debug_handler, extract::State, response::Response, Extension};
+use axum_messages::Messages;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::{
+    controller::{format, INVITATION_TTL_DAYS},
+    domain::Role,
+    startup::AppState,
+    Result,
+};
+
+#[derive(FromRow, Serialize)]
+struct AdminUser {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    is_active: bool,
+}
+
+#[derive(FromRow, Serialize)]
+struct PendingInvitation {
+    email: String,
+    role: String,
+    invited_by: String,
+    created_at: DateTime<Utc>,
+}
+
+#[debug_handler]
+pub async fn users(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    let users: Vec<AdminUser> = sqlx::query_as(
+        r#"
+        SELECT user_id, username, email, role, is_active
+        FROM users
+        ORDER BY is_active DESC, username
+        "#,
+    )
+    .fetch_all(state.db_pool.as_ref())
+    .await?;
+    let invitations: Vec<PendingInvitation> = sqlx::query_as(
+        r#"
+        SELECT i.email, i.role, u.username AS invited_by, i.created_at
+        FROM user_invitations i
+        JOIN users u ON u.user_id = i.invited_by
+        WHERE i.accepted_at IS NULL AND i.created_at > now() - make_interval(days => $1)
+        ORDER BY i.created_at DESC
+        "#,
+    )
+    .bind(INVITATION_TTL_DAYS)
+    .fetch_all(state.db_pool.as_ref())
+    .await?;
+    format::render().view(
+        &state.tera_engine,
+        "admin/users.html",
+        json!({
+            "messages": messages,
+            "users": users,
+            "invitations": invitations,
+            "roles": Role::ALL.map(|role| role.as_str()),
+            "current_user_id": user_id,
+        }),
+    )
+}
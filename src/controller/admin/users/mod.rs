@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::users;
+pub use post::{deactivate_user, invite_user, reactivate_user};
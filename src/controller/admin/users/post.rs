@@ -0,0 +1,137 @@
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::Response,
+    Extension, Form,
+};
+use axum_messages::Messages;
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    authentication::hash_token,
+    controller::{format, generate_subscription_token, INVITATION_TTL_DAYS},
+    domain::{Role, SubscriberEmail},
+    startup::AppState,
+    Result,
+};
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    email: String,
+    role: String,
+}
+
+#[debug_handler]
+pub async fn invite_user(
+    Extension(user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Form(params): Form<InvitationFormData>,
+) -> Result<Response> {
+    let email = match SubscriberEmail::parse(params.email) {
+        Ok(email) => email,
+        Err(e) => {
+            messages.error(e.to_string());
+            return format::render().redirect("/admin/users");
+        }
+    };
+    let role = match Role::parse(&params.role) {
+        Ok(role) => role,
+        Err(e) => {
+            messages.error(e.to_string());
+            return format::render().redirect("/admin/users");
+        }
+    };
+    // Only the hash is stored, the token itself only ever appears in the email.
+    let token = generate_subscription_token();
+    sqlx::query(
+        r#"
+        INSERT INTO user_invitations (invitation_id, email, role, token_hash, invited_by, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(email.as_ref())
+    .bind(role.as_str())
+    .bind(hash_token(&token))
+    .bind(user_id)
+    .bind(Utc::now())
+    .execute(state.db_pool.as_ref())
+    .await?;
+
+    let accept_link = format!("{}/invitations/accept?token={}", state.base_url, token);
+    let body = state.tera_engine.render_email(
+        "invitation",
+        json!({
+            "role": role.as_str(),
+            "accept_link": accept_link,
+            "ttl_days": INVITATION_TTL_DAYS,
+        }),
+    )?;
+    let sent_to = format!("An invitation has been sent to {}.", email.as_ref());
+    state
+        .email_client
+        .send_email(
+            email,
+            "You have been invited to administer the newsletter",
+            &body.html,
+            &body.text,
+            None,
+        )
+        .await?;
+    messages.info(sent_to);
+    format::render().redirect("/admin/users")
+}
+
+/// Stops a user from logging in. Users are never deleted, so the
+/// idempotency records of the issues they published are kept.
+#[debug_handler]
+pub async fn deactivate_user(
+    Extension(current_user_id): Extension<Uuid>,
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response> {
+    if user_id == current_user_id {
+        messages.error("You can't deactivate your own account.");
+        return format::render().redirect("/admin/users");
+    }
+    set_active(&state, messages, user_id, false).await
+}
+
+#[debug_handler]
+pub async fn reactivate_user(
+    messages: Messages,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response> {
+    set_active(&state, messages, user_id, true).await
+}
+
+async fn set_active(
+    state: &AppState,
+    messages: Messages,
+    user_id: Uuid,
+    is_active: bool,
+) -> Result<Response> {
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"
+        UPDATE users SET is_active = $2 WHERE user_id = $1
+        RETURNING username
+        "#,
+    )
+    .bind(user_id)
+    .bind(is_active)
+    .fetch_optional(state.db_pool.as_ref())
+    .await?;
+    match row {
+        Some((username,)) if is_active => {
+            messages.info(format!("{} has been reactivated.", username))
+        }
+        Some((username,)) => messages.info(format!("{} has been deactivated.", username)),
+        None => messages.error("There is no such user."),
+    };
+    format::render().redirect("/admin/users")
+}
@@ -0,0 +1,122 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    middleware::{ClientIp, Zero2prodRequestId},
+    startup::AppState,
+};
+
+/// Where a signup came from when the form doesn't say.
+pub const DEFAULT_CONSENT_SOURCE: &str = "subscription_form";
+const MAX_SOURCE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentEventType {
+    Signup,
+    Confirmation,
+    /// An admin confirmed the subscriber on their behalf, which is not
+    /// consent given by the subscriber.
+    AdminOverride,
+}
+
+impl ConsentEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventType::Signup => "signup",
+            ConsentEventType::Confirmation => "confirmation",
+            ConsentEventType::AdminOverride => "admin_override",
+        }
+    }
+}
+
+/// The request details kept as evidence of consent.
+#[derive(Debug, Clone)]
+pub struct ConsentMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for ConsentMetadata {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip_address) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string);
+        let request_id = parts
+            .extensions
+            .get::<Zero2prodRequestId>()
+            .map(|r| r.get().to_string());
+        Ok(Self {
+            ip_address: ip_address.map(|ip| ip.to_string()),
+            user_agent,
+            request_id,
+        })
+    }
+}
+
+/// Trims a client supplied signup source, falling back to the default one.
+pub fn consent_source(source: Option<&str>) -> String {
+    match source.map(str::trim) {
+        Some(source) if !source.is_empty() => source.chars().take(MAX_SOURCE_LEN).collect(),
+        _ => DEFAULT_CONSENT_SOURCE.to_string(),
+    }
+}
+
+/// Records the same consent event for each subscriber, with the address
+/// they have at the time.
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+    event_type: ConsentEventType,
+    source: &str,
+    metadata: &ConsentMetadata,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        r#"
+    INSERT INTO consent_events (
+        consent_event_id,
+        subscriber_id,
+        email,
+        event_type,
+        source,
+        ip_address,
+        user_agent,
+        request_id,
+        occurred_at
+    )
+    SELECT t.consent_event_id, s.id, s.email, $3, $4, $5, $6, $7, now()
+    FROM UNNEST($1::uuid[], $2::uuid[]) AS t(consent_event_id, subscriber_id)
+    JOIN subscriptions s ON s.id = t.subscriber_id
+    "#,
+    )
+    .bind(
+        subscriber_ids
+            .iter()
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<_>>(),
+    )
+    .bind(subscriber_ids)
+    .bind(event_type.as_str())
+    .bind(source)
+    .bind(&metadata.ip_address)
+    .bind(&metadata.user_agent)
+    .bind(&metadata.request_id);
+    transaction.execute(query).await?;
+    Ok(())
+}
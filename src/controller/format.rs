@@ -46,6 +46,13 @@ impl RenderBuilder {
         }
     }
 
+    #[must_use]
+    pub fn status(self, status: StatusCode) -> Self {
+        Self {
+            response: self.response.status(status),
+        }
+    }
+
     pub fn view<S>(self, v: &TeraView, key: &str, data: S) -> Result<Response>
     where
         S: Serialize,
@@ -0,0 +1,170 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    response::Response,
+    Form,
+};
+use axum_messages::Messages;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{hash_password, hash_token, password_policy_violation},
+    startup::AppState,
+    Result,
+};
+
+use super::format;
+
+/// How long an invitation link can be used for.
+pub const INVITATION_TTL_DAYS: i32 = 7;
+
+#[derive(Deserialize)]
+pub struct InvitationParameters {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInvitationForm {
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[derive(FromRow)]
+struct Invitation {
+    invitation_id: Uuid,
+    email: String,
+    role: String,
+    created_at: DateTime<Utc>,
+    accepted_at: Option<DateTime<Utc>>,
+}
+
+impl Invitation {
+    fn is_usable(&self) -> bool {
+        self.accepted_at.is_none()
+            && self.created_at + Duration::days(i64::from(INVITATION_TTL_DAYS)) > Utc::now()
+    }
+}
+
+#[debug_handler]
+pub async fn accept_invitation_form(
+    messages: Messages,
+    State(state): State<AppState>,
+    Query(params): Query<InvitationParameters>,
+) -> Result<Response> {
+    let mut transaction = state.db_pool.begin().await?;
+    let invitation = get_invitation(&mut transaction, &params.token).await?;
+    transaction.commit().await?;
+    let Some(invitation) = invitation.filter(Invitation::is_usable) else {
+        return invalid_invitation(&state);
+    };
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    format::render().view(
+        &state.tera_engine,
+        "invitations/accept.html",
+        json!({
+            "messages": messages,
+            "valid": true,
+            "token": params.token,
+            "email": invitation.email,
+            "role": invitation.role,
+        }),
+    )
+}
+
+#[debug_handler]
+pub async fn accept_invitation(
+    messages: Messages,
+    State(state): State<AppState>,
+    Query(params): Query<InvitationParameters>,
+    Form(form): Form<AcceptInvitationForm>,
+) -> Result<Response> {
+    let form_path = format!("/invitations/accept?token={}", params.token);
+    let username = form.username.trim();
+    if username.is_empty() {
+        messages.error("The username can't be empty.");
+        return format::render().redirect(&form_path);
+    }
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        messages.error("You entered two different passwords - the field values must match.");
+        return format::render().redirect(&form_path);
+    }
+    if let Some(reason) =
+        password_policy_violation(&state.password_policy, &form.password, &[username]).await?
+    {
+        messages.error(reason);
+        return format::render().redirect(&form_path);
+    }
+    let password_hash = hash_password(form.password).await?;
+
+    let mut transaction = state.db_pool.begin().await?;
+    let Some(invitation) = get_invitation(&mut transaction, &params.token)
+        .await?
+        .filter(Invitation::is_usable)
+    else {
+        return invalid_invitation(&state);
+    };
+    let query = sqlx::query(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(username)
+    .bind(password_hash.expose_secret())
+    .bind(&invitation.email)
+    .bind(&invitation.role);
+    if transaction.execute(query).await?.rows_affected() == 0 {
+        messages.error(format!("The username {} is already taken.", username));
+        return format::render().redirect(&form_path);
+    }
+    let query = sqlx::query(
+        r#"
+        UPDATE user_invitations SET accepted_at = now() WHERE invitation_id = $1
+        "#,
+    )
+    .bind(invitation.invitation_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    messages.info("Your account is ready, you can now log in.");
+    format::render().redirect("/login")
+}
+
+/// Locks the invitation row, so it can't be accepted twice concurrently.
+async fn get_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Invitation>> {
+    let invitation = sqlx::query_as(
+        r#"
+        SELECT invitation_id, email, role, created_at, accepted_at
+        FROM user_invitations
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(invitation)
+}
+
+fn invalid_invitation(state: &AppState) -> Result<Response> {
+    format::render().status(StatusCode::UNAUTHORIZED).view(
+        &state.tera_engine,
+        "invitations/accept.html",
+        json!({"valid": false}),
+    )
+}
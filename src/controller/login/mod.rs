@@ -1,8 +1,14 @@
 mod post;
+mod reset;
+mod throttle;
+mod two_factor;
 use axum::{debug_handler, extract::State, response::Response};
 use axum_messages::Messages;
 pub use post::*;
+pub use reset::*;
 use serde_json::json;
+pub use throttle::*;
+pub use two_factor::*;
 
 use crate::{startup::AppState, Result};
 
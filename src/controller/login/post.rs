@@ -2,36 +2,86 @@ use axum::{debug_handler, extract::State, response::Response, Form};
 use axum_messages::Messages;
 use axum_session::Session;
 use axum_session_redispool::SessionRedisPool;
+use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
-    authentication::{validate_credentials, Credentials},
+    authentication::{get_session_epoch, get_totp_secret, validate_credentials, Credentials},
     controller::format,
     domain::LoginForm,
+    errors::Error,
+    middleware::ClientIp,
     startup::AppState,
     Result,
 };
 
+use super::{
+    clear_login_failures, delay_failed_login, forget_login_attempt, lockout_message,
+    start_login_attempt, LoginAttempt,
+};
+
 #[debug_handler]
 pub async fn login(
     session: Session<SessionRedisPool>,
     messages: Messages,
     State(state): State<AppState>,
+    ClientIp(ip_address): ClientIp,
     Form(params): Form<LoginForm>,
 ) -> Result<Response> {
+    let username = params.username;
+    // Locked out attempts aren't even checked, so the right password can't be
+    // told apart from a wrong one.
+    let n_failures =
+        match start_login_attempt(&state.redis_pool, &state.login, &username, ip_address).await? {
+            LoginAttempt::LockedOut(remaining) => {
+                messages.error(lockout_message(remaining));
+                return format::render().redirect("/login");
+            }
+            LoginAttempt::Allowed { n_failures } => n_failures,
+        };
     let credentials = Credentials {
-        username: params.username,
+        username: username.clone(),
         password: params.password,
     };
     let res = validate_credentials(credentials, &state.db_pool).await;
     match res {
         Ok(user_id) => {
-            session.renew();
-            session.set("user_id", user_id);
+            // The password alone doesn't log in users with two-factor
+            // authentication, they are only remembered until they give a code.
+            if get_totp_secret(user_id, &state.db_pool).await?.is_some() {
+                forget_login_attempt(&state.redis_pool, &state.login, &username, ip_address)
+                    .await?;
+                session.renew();
+                session.remove("user_id");
+                session.set("two_factor_user_id", user_id);
+                return format::render().redirect("/login/two-factor");
+            }
+            clear_login_failures(&state.redis_pool, &state.login, &username, ip_address).await?;
+            start_session(&session, user_id, &state.db_pool).await?;
             format::render().redirect("/admin/dashboard")
         }
+        Err(e @ Error::Unauthorized(_)) => {
+            delay_failed_login(&state.login, n_failures).await;
+            messages.error(e.to_string());
+            format::render().redirect("/login")
+        }
         Err(e) => {
             messages.error(e.to_string());
             format::render().redirect("/login")
         }
     }
 }
+
+/// Logs the user in, once they have proven who they are.
+pub async fn start_session(
+    session: &Session<SessionRedisPool>,
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<()> {
+    let session_epoch = get_session_epoch(user_id, pool).await?;
+    session.renew();
+    session.remove("two_factor_user_id");
+    session.set("user_id", user_id);
+    session.set("session_epoch", session_epoch);
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    response::Response,
+    Form,
+};
+use axum_messages::Messages;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Executor, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{change_password_store, hash_password, hash_token, password_policy_violation},
+    controller::{
+        admin::{get_user_email, get_username, send_password_changed_email},
+        format, generate_subscription_token,
+    },
+    domain::SubscriberEmail,
+    middleware::ClientIp,
+    startup::AppState,
+    Result,
+};
+
+use super::allow_password_reset_request;
+
+/// How long a password reset link can be used for.
+pub const PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequestForm {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetParameters {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetForm {
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[derive(FromRow)]
+struct ResetToken {
+    user_id: Uuid,
+    created_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+impl ResetToken {
+    fn is_usable(&self) -> bool {
+        self.used_at.is_none()
+            && self.created_at + Duration::minutes(PASSWORD_RESET_TTL_MINUTES) > Utc::now()
+    }
+}
+
+#[debug_handler]
+pub async fn password_reset_request_form(
+    messages: Messages,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    format::render().view(
+        &state.tera_engine,
+        "password_reset_request.html",
+        json!({"messages": messages}),
+    )
+}
+
+/// Emails a reset link to the admins using `email`. The answer is the same
+/// whether or not there are any, so the form can't be used to find accounts;
+/// the emails are sent after the response, so neither can its timing.
+#[debug_handler]
+pub async fn request_password_reset(
+    messages: Messages,
+    State(state): State<AppState>,
+    ClientIp(ip_address): ClientIp,
+    Form(params): Form<PasswordResetRequestForm>,
+) -> Result<Response> {
+    let email = params.email.trim().to_string();
+    if !allow_password_reset_request(&state.redis_pool, &state.login, &email, ip_address).await? {
+        messages.error("Too many password reset requests, please try again later.");
+        return format::render().redirect("/login/forgot");
+    }
+    tokio::spawn(send_password_reset_emails(state, email).instrument(tracing::Span::current()));
+    messages.info("If an account uses this address, we have sent it a link to reset the password.");
+    format::render().redirect("/login")
+}
+
+async fn send_password_reset_emails(state: AppState, email: String) {
+    let users: Vec<(Uuid, String, String)> = match sqlx::query_as(
+        r#"
+        SELECT user_id, username, email
+        FROM users
+        WHERE lower(email) = lower($1) AND is_active
+        "#,
+    )
+    .bind(&email)
+    .fetch_all(state.db_pool.as_ref())
+    .await
+    {
+        Ok(users) => users,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to look up the accounts of a password reset request",
+            );
+            return;
+        }
+    };
+    for (user_id, username, email) in users {
+        if let Err(e) = send_password_reset_email(&state, user_id, &username, email).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the password reset email",
+            );
+        }
+    }
+}
+
+async fn send_password_reset_email(
+    state: &AppState,
+    user_id: Uuid,
+    username: &str,
+    email: String,
+) -> Result<()> {
+    let email = SubscriberEmail::parse(email)?;
+    let token = generate_subscription_token();
+    // A new link replaces any the user was sent before.
+    let mut transaction = state.db_pool.begin().await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM password_reset_tokens WHERE user_id = $1 AND used_at IS NULL
+        "#,
+    )
+    .bind(user_id);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(hash_token(&token))
+    .bind(user_id)
+    .bind(Utc::now());
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    let reset_link = format!("{}/login/reset?token={}", state.base_url, token);
+    let body = state.tera_engine.render_email(
+        "password_reset",
+        json!({
+            "username": username,
+            "reset_link": reset_link,
+            "ttl_minutes": PASSWORD_RESET_TTL_MINUTES,
+        }),
+    )?;
+    state
+        .email_client
+        .send_email(email, "Reset your password", &body.html, &body.text, None)
+        .await?;
+    Ok(())
+}
+
+#[debug_handler]
+pub async fn password_reset_form(
+    messages: Messages,
+    State(state): State<AppState>,
+    Query(params): Query<PasswordResetParameters>,
+) -> Result<Response> {
+    let mut transaction = state.db_pool.begin().await?;
+    let token = get_reset_token(&mut transaction, &params.token).await?;
+    transaction.commit().await?;
+    if !token.is_some_and(|token| token.is_usable()) {
+        return invalid_reset_link(&state);
+    }
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    format::render().view(
+        &state.tera_engine,
+        "password_reset.html",
+        json!({"messages": messages, "valid": true, "token": params.token}),
+    )
+}
+
+#[debug_handler]
+pub async fn reset_password(
+    messages: Messages,
+    State(state): State<AppState>,
+    Query(params): Query<PasswordResetParameters>,
+    Form(form): Form<PasswordResetForm>,
+) -> Result<Response> {
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        messages.error("You entered two different new passwords - the field values must match.");
+        return format::render().redirect(&format!("/login/reset?token={}", params.token));
+    }
+    // Checking and hashing the password is slow, so it happens before the
+    // token is locked, and the token is checked again once it is.
+    let mut transaction = state.db_pool.begin().await?;
+    let token = get_reset_token(&mut transaction, &params.token).await?;
+    transaction.commit().await?;
+    let Some(token) = token.filter(ResetToken::is_usable) else {
+        return invalid_reset_link(&state);
+    };
+    let username = get_username(token.user_id, &state.db_pool).await?;
+    let email = get_user_email(token.user_id, &state.db_pool).await?;
+    let user_inputs = [Some(username.as_str()), email.as_deref()];
+    if let Some(reason) = password_policy_violation(
+        &state.password_policy,
+        &form.new_password,
+        &user_inputs.into_iter().flatten().collect::<Vec<_>>(),
+    )
+    .await?
+    {
+        messages.error(reason);
+        return format::render().redirect(&format!("/login/reset?token={}", params.token));
+    }
+    let password_hash = hash_password(form.new_password).await?;
+
+    // The link is only used up if the password changes and older sessions
+    // end with it.
+    let mut transaction = state.db_pool.begin().await?;
+    let Some(token) = get_reset_token(&mut transaction, &params.token)
+        .await?
+        .filter(ResetToken::is_usable)
+    else {
+        return invalid_reset_link(&state);
+    };
+    let query = sqlx::query(
+        r#"
+        UPDATE password_reset_tokens SET used_at = now() WHERE token_hash = $1
+        "#,
+    )
+    .bind(hash_token(&params.token));
+    transaction.execute(query).await?;
+    change_password_store(&mut transaction, token.user_id, password_hash).await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE users SET session_epoch = session_epoch + 1 WHERE user_id = $1
+        "#,
+    )
+    .bind(token.user_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    if let Err(e) = send_password_changed_email(&state, token.user_id, &username).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send the password changed notice",
+        );
+    }
+    messages.info("Your password has been reset, you can now log in.");
+    format::render().redirect("/login")
+}
+
+/// Locks the token row, so it can't be used twice concurrently.
+async fn get_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<ResetToken>> {
+    let token = sqlx::query_as(
+        r#"
+        SELECT user_id, created_at, used_at
+        FROM password_reset_tokens
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(token)
+}
+
+fn invalid_reset_link(state: &AppState) -> Result<Response> {
+    format::render().status(StatusCode::UNAUTHORIZED).view(
+        &state.tera_engine,
+        "password_reset.html",
+        json!({"valid": false}),
+    )
+}
@@ -0,0 +1,164 @@
+use std::{net::IpAddr, time::Duration};
+
+use redis::AsyncCommands;
+use redis_pool::SingleRedisPool;
+
+use crate::{configuration::LoginSettings, Result};
+
+/// The failed login counters an attempt counts towards, with their limits.
+/// Usernames are counted regardless of case, like people type them.
+fn counters(settings: &LoginSettings, username: &str, ip: Option<IpAddr>) -> Vec<(String, u32)> {
+    let mut counters = vec![(
+        format!("login_failures:username:{}", username.to_lowercase()),
+        settings.max_failures_per_username,
+    )];
+    if let Some(ip) = ip {
+        counters.push((
+            format!("login_failures:ip:{}", ip),
+            settings.max_failures_per_ip,
+        ));
+    }
+    counters
+}
+
+/// Whether a login attempt may go ahead.
+pub enum LoginAttempt {
+    /// The attempt is over a limit; logins stay locked out for this long.
+    LockedOut(Duration),
+    /// The attempt may be checked, and makes this many failures in a row if
+    /// it fails.
+    Allowed { n_failures: u32 },
+}
+
+/// Counts a login attempt as failed before it is checked, so concurrent
+/// guesses can't all get past the limits while the first ones are still being
+/// verified. Successful attempts are taken off again with
+/// [`clear_login_failures`] or [`forget_login_attempt`]. Counters reaching
+/// their limit lock logins out for a while.
+pub async fn start_login_attempt(
+    redis_pool: &SingleRedisPool,
+    settings: &LoginSettings,
+    username: &str,
+    ip: Option<IpAddr>,
+) -> Result<LoginAttempt> {
+    let mut connection = redis_pool.acquire().await?;
+    let mut most_failures = 0;
+    let mut lockout = None;
+    for (key, max_failures) in counters(settings, username, ip) {
+        let n_failures: u32 = connection.incr(&key, 1).await?;
+        if n_failures == 1 {
+            let _: () = connection
+                .expire(&key, settings.failure_window().as_secs() as i64)
+                .await?;
+        }
+        if n_failures == max_failures {
+            let _: () = connection
+                .expire(&key, settings.lockout().as_secs() as i64)
+                .await?;
+        }
+        if n_failures > max_failures {
+            let ttl: i64 = connection.ttl(&key).await?;
+            let remaining = Duration::from_secs(ttl.max(1) as u64);
+            lockout = lockout.max(Some(remaining));
+        }
+        most_failures = most_failures.max(n_failures);
+    }
+    Ok(match lockout {
+        Some(remaining) => LoginAttempt::LockedOut(remaining),
+        None => LoginAttempt::Allowed {
+            n_failures: most_failures,
+        },
+    })
+}
+
+/// Answers a failed login more slowly the more failures there were in a row.
+pub async fn delay_failed_login(settings: &LoginSettings, n_failures: u32) {
+    tokio::time::sleep(settings.delay(n_failures)).await;
+}
+
+/// Takes a login attempt that turned out right off the counters again.
+pub async fn forget_login_attempt(
+    redis_pool: &SingleRedisPool,
+    settings: &LoginSettings,
+    username: &str,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let mut connection = redis_pool.acquire().await?;
+    for (key, _) in counters(settings, username, ip) {
+        decrement(&mut connection, &key).await?;
+    }
+    Ok(())
+}
+
+/// A successful login forgives the username its earlier failures. The client
+/// address only has this attempt taken off, so one valid account can't shield
+/// a spray of guesses at others.
+pub async fn clear_login_failures(
+    redis_pool: &SingleRedisPool,
+    settings: &LoginSettings,
+    username: &str,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let mut connection = redis_pool.acquire().await?;
+    let mut counters = counters(settings, username, ip).into_iter();
+    if let Some((key, _)) = counters.next() {
+        let _: () = connection.del(key).await?;
+    }
+    for (key, _) in counters {
+        decrement(&mut connection, &key).await?;
+    }
+    Ok(())
+}
+
+/// Decrements a counter, deleting it rather than going below zero, as it may
+/// have expired in the meantime.
+async fn decrement(connection: &mut impl AsyncCommands, key: &str) -> Result<()> {
+    let n_failures: i64 = connection.incr(key, -1).await?;
+    if n_failures <= 0 {
+        let _: () = connection.del(key).await?;
+    }
+    Ok(())
+}
+
+/// Counts a request for a password reset link, returning whether it is within
+/// the limits for the address and the client. Every request counts, whether
+/// or not an account uses the address, so the limits reveal nothing.
+pub async fn allow_password_reset_request(
+    redis_pool: &SingleRedisPool,
+    settings: &LoginSettings,
+    email: &str,
+    ip: Option<IpAddr>,
+) -> Result<bool> {
+    let mut counters = vec![(
+        format!("password_resets:email:{}", email.to_lowercase()),
+        settings.max_password_resets_per_email,
+    )];
+    if let Some(ip) = ip {
+        counters.push((
+            format!("password_resets:ip:{}", ip),
+            settings.max_password_resets_per_ip,
+        ));
+    }
+    let mut connection = redis_pool.acquire().await?;
+    let mut allowed = true;
+    for (key, max_requests) in counters {
+        let n_requests: u32 = connection.incr(&key, 1).await?;
+        if n_requests == 1 {
+            let _: () = connection
+                .expire(&key, settings.password_reset_window().as_secs() as i64)
+                .await?;
+        }
+        allowed &= n_requests <= max_requests;
+    }
+    Ok(allowed)
+}
+
+/// Tells the user how long to wait, rounded up to the minute.
+pub fn lockout_message(remaining: Duration) -> String {
+    let minutes = remaining.as_secs().div_ceil(60);
+    format!(
+        "Too many failed login attempts, please try again in {} minute{}.",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    )
+}
@@ -0,0 +1,77 @@
+use axum::{debug_handler, extract::State, response::Response, Form};
+use axum_messages::Messages;
+use axum_session::Session;
+use axum_session_redispool::SessionRedisPool;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    authentication::verify_second_factor,
+    controller::{admin::get_username, format},
+    middleware::ClientIp,
+    startup::AppState,
+    Result,
+};
+
+use super::{
+    clear_login_failures, delay_failed_login, lockout_message, start_login_attempt, start_session,
+    LoginAttempt,
+};
+
+#[derive(Deserialize)]
+pub struct TwoFactorForm {
+    code: String,
+}
+
+#[debug_handler]
+pub async fn two_factor_form(
+    session: Session<SessionRedisPool>,
+    messages: Messages,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    if session.get::<Uuid>("two_factor_user_id").is_none() {
+        return format::render().redirect("/login");
+    }
+    let messages = messages
+        .into_iter()
+        .map(|msg| format!("{}", msg))
+        .collect::<Vec<_>>();
+    format::render().view(
+        &state.tera_engine,
+        "login_two_factor.html",
+        json!({"messages": messages}),
+    )
+}
+
+/// The second login step, for users who gave the right password.
+#[debug_handler]
+pub async fn verify_two_factor(
+    session: Session<SessionRedisPool>,
+    messages: Messages,
+    State(state): State<AppState>,
+    ClientIp(ip_address): ClientIp,
+    Form(params): Form<TwoFactorForm>,
+) -> Result<Response> {
+    let Some(user_id) = session.get::<Uuid>("two_factor_user_id") else {
+        return format::render().redirect("/login");
+    };
+    // Wrong codes count towards the same limits as wrong passwords.
+    let username = get_username(user_id, &state.db_pool).await?;
+    let n_failures =
+        match start_login_attempt(&state.redis_pool, &state.login, &username, ip_address).await? {
+            LoginAttempt::LockedOut(remaining) => {
+                messages.error(lockout_message(remaining));
+                return format::render().redirect("/login/two-factor");
+            }
+            LoginAttempt::Allowed { n_failures } => n_failures,
+        };
+    if !verify_second_factor(user_id, &params.code, &state.db_pool).await? {
+        delay_failed_login(&state.login, n_failures).await;
+        messages.error("The code is invalid or has already been used.");
+        return format::render().redirect("/login/two-factor");
+    }
+    clear_login_failures(&state.redis_pool, &state.login, &username, ip_address).await?;
+    start_session(&session, user_id, &state.db_pool).await?;
+    format::render().redirect("/admin/dashboard")
+}
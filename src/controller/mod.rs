@@ -1,15 +1,29 @@
 mod admin;
+mod consent;
 mod format;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod subscriptions_protection;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use consent::*;
 pub use format::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_protection::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
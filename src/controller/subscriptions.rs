@@ -6,7 +6,8 @@ use axum::{
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
-use sqlx::{Executor, Postgres, Transaction};
+use serde_json::json;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
@@ -14,27 +15,109 @@ use crate::{
     email_client::EmailClient,
     errors,
     startup::AppState,
+    view_engine::TeraView,
     Result,
 };
 
-use super::format;
+use super::{
+    check_signup_rate, consent_source, default_list_id, format, record_consent,
+    redeem_proof_of_work, verify_proof_of_work, ConsentEventType, ConsentMetadata,
+};
 
 #[derive(Deserialize)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    pub list_id: Option<Uuid>,
+    /// Which form or campaign the signup came from.
+    pub source: Option<String>,
+    /// A honeypot: hidden from people, so only bots fill it in.
+    pub website: Option<String>,
+    pub pow_challenge: Option<String>,
+    pub pow_nonce: Option<String>,
 }
 
 #[debug_handler]
 pub async fn subscribe(
     State(state): State<AppState>,
+    metadata: ConsentMetadata,
     Form(params): Form<FormData>,
 ) -> Result<Response> {
-    let new_subscriber = params.try_into()?;
+    // Look like a success, so bots don't learn to leave the field alone.
+    if params.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        tracing::warn!("Ignored a signup with the honeypot field filled in");
+        return format::empty();
+    }
+    let list_id = match params.list_id {
+        Some(list_id) if list_exists(&state.db_pool, list_id).await? => list_id,
+        Some(_) => return Err(errors::Error::BadRequest("Unknown mailing list.".into())),
+        None => default_list_id(&state.db_pool).await?,
+    };
+    let source = consent_source(params.source.as_deref());
+    // The proof of work is for the address exactly as submitted.
+    let submitted_email = params.email.clone();
+    let pow_challenge = params.pow_challenge.clone();
+    let pow_nonce = params.pow_nonce.clone();
+    let new_subscriber: NewSubscriber = params.try_into()?;
+    let proof_of_work = verify_proof_of_work(
+        &state,
+        pow_challenge.as_deref(),
+        &submitted_email,
+        pow_nonce.as_deref(),
+    )?;
+    check_signup_rate(
+        &state.db_pool,
+        &state.subscriptions,
+        metadata.ip_address.as_deref(),
+        new_subscriber.email.as_ref(),
+    )
+    .await?;
+    // Only a signup that is otherwise good uses up its challenge, so fixing a
+    // rejected form doesn't take another proof of work.
+    if let Some(proof_of_work) = &proof_of_work {
+        redeem_proof_of_work(&state.db_pool, proof_of_work).await?;
+    }
 
     let mut transaction = state.db_pool.begin().await?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber).await?;
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => match reset_existing_subscriber(&mut transaction, &new_subscriber).await? {
+            Some(subscriber_id) => subscriber_id,
+            // Respond as for a new address so the endpoint doesn't reveal who
+            // is already subscribed. Confirmed addresses only join another
+            // list once they click the link we send them, so nobody can sign
+            // a stranger up.
+            None => {
+                let list_join =
+                    request_list_join(&mut transaction, &new_subscriber, list_id).await?;
+                if let Some(list_join) = &list_join {
+                    record_consent(
+                        &mut transaction,
+                        &[list_join.subscriber_id],
+                        ConsentEventType::Signup,
+                        &source,
+                        &metadata,
+                    )
+                    .await?;
+                }
+                transaction.commit().await?;
+                if let Some(list_join) = list_join {
+                    send_list_join_email(&state, new_subscriber.email, &list_join).await?;
+                }
+                return format::empty();
+            }
+        },
+    };
+    join_list(&mut transaction, subscriber_id, list_id).await?;
+    record_consent(
+        &mut transaction,
+        &[subscriber_id],
+        ConsentEventType::Signup,
+        &source,
+        &metadata,
+    )
+    .await?;
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token).await?;
 
@@ -42,6 +125,7 @@ pub async fn subscribe(
 
     send_confirm_email(
         &state.email_client,
+        &state.tera_engine,
         new_subscriber,
         &state.base_url,
         &subscription_token,
@@ -52,6 +136,7 @@ pub async fn subscribe(
 
 pub async fn send_confirm_email(
     email_client: &EmailClient,
+    tera_engine: &TeraView,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
@@ -60,39 +145,181 @@ pub async fn send_confirm_email(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!<br /> \
-            Click <a href=\"{}\">here</a> to confirm your subscription",
-        confirmation_link
-    );
-    let html_body = &format!(
-        "Welcome to our newsletter!<br /> \
-            Click <a href=\"{}\">here</a> to confirm your subscription",
-        confirmation_link
-    );
+    let body = tera_engine.render_email(
+        "confirmation",
+        json!({
+            "name": new_subscriber.name.as_ref(),
+            "confirmation_link": confirmation_link,
+        }),
+    )?;
     email_client
-        .send_email(new_subscriber.email, "Welcome!", html_body, &plain_body)
+        .send_email(
+            new_subscriber.email,
+            "Welcome!",
+            &body.html,
+            &body.text,
+            None,
+        )
         .await?;
     Ok(())
 }
 
+/// Inserts a pending subscriber, returning `None` if the email is already
+/// registered.
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    let query = sqlx::query(
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id: Option<(Uuid,)> = sqlx::query_as(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO NOTHING
+    RETURNING id
     "#,
     )
-    .bind(subscriber_id)
+    .bind(Uuid::new_v4())
     .bind(new_subscriber.email.as_ref())
     .bind(new_subscriber.name.as_ref())
-    .bind(Utc::now());
+    .bind(Utc::now())
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(subscriber_id.map(|r| r.0))
+}
+
+/// Decides whether an already registered email gets a new confirmation
+/// email: pending subscribers do, and so do unsubscribed ones, which go back
+/// to pending. Confirmed and suppressed subscribers don't.
+async fn reset_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id: Option<(Uuid,)> = sqlx::query_as(
+        r#"
+    UPDATE subscriptions
+    SET status = 'pending_confirmation'
+    WHERE email = $1 AND status IN ('pending_confirmation', 'unsubscribed')
+    RETURNING id
+    "#,
+    )
+    .bind(new_subscriber.email.as_ref())
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(subscriber_id.map(|r| r.0))
+}
+
+pub async fn list_exists(pool: &PgPool, list_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row: Option<(Uuid,)> = sqlx::query_as(
+        r#"
+    SELECT list_id FROM mailing_lists WHERE list_id = $1
+    "#,
+    )
+    .bind(list_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        r#"
+    INSERT INTO list_memberships (subscriber_id, list_id)
+    VALUES ($1, $2)
+    ON CONFLICT DO NOTHING
+    "#,
+    )
+    .bind(subscriber_id)
+    .bind(list_id);
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// A confirmed subscriber's pending request to join another list.
+struct ListJoinRequest {
+    subscriber_id: Uuid,
+    name: String,
+    list_name: String,
+    subscription_token: String,
+}
+
+/// Stores a token that adds a confirmed subscriber to `list_id` once they
+/// click it, returning `None` if the address isn't confirmed or is already a
+/// member.
+async fn request_list_join(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    list_id: Uuid,
+) -> Result<Option<ListJoinRequest>> {
+    let subscriber: Option<(Uuid, String, String)> = sqlx::query_as(
+        r#"
+    SELECT s.id, s.name, l.name
+    FROM subscriptions s
+    JOIN mailing_lists l ON l.list_id = $2
+    WHERE s.email = $1
+        AND s.status = 'confirmed'
+        AND NOT EXISTS (
+            SELECT 1 FROM list_memberships m WHERE m.subscriber_id = s.id AND m.list_id = $2
+        )
+    "#,
+    )
+    .bind(new_subscriber.email.as_ref())
+    .bind(list_id)
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some((subscriber_id, name, list_name)) = subscriber else {
+        return Ok(None);
+    };
+    let subscription_token = generate_subscription_token();
+    let query = sqlx::query(
+        r#"
+    INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+    VALUES ($1, $2, $3)
+    "#,
+    )
+    .bind(&subscription_token)
+    .bind(subscriber_id)
+    .bind(list_id);
     transaction.execute(query).await?;
-    Ok(subscriber_id)
+    Ok(Some(ListJoinRequest {
+        subscriber_id,
+        name,
+        list_name,
+        subscription_token,
+    }))
+}
+
+async fn send_list_join_email(
+    state: &AppState,
+    email: SubscriberEmail,
+    list_join: &ListJoinRequest,
+) -> Result<()> {
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        state.base_url, list_join.subscription_token
+    );
+    let body = state.tera_engine.render_email(
+        "list_join",
+        json!({
+            "name": list_join.name,
+            "list_name": list_join.list_name,
+            "confirmation_link": confirmation_link,
+        }),
+    )?;
+    state
+        .email_client
+        .send_email(
+            email,
+            &format!("Confirm your subscription to {}", list_join.list_name),
+            &body.html,
+            &body.text,
+            None,
+        )
+        .await?;
+    Ok(())
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -105,7 +332,7 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
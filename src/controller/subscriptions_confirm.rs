@@ -1,77 +1,317 @@
 use axum::{
     debug_handler,
     extract::{Query, State},
-    response::{IntoResponse, Response},
+    response::Response,
 };
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
 use serde::Deserialize;
-use sqlx::PgPool;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Executor, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::startup::AppState;
+use crate::{domain::SubscriberEmail, startup::AppState, Result};
+
+use super::{
+    format, join_list, preferences_link, record_consent, unsubscribe_link, ConsentEventType,
+    ConsentMetadata,
+};
 
 #[derive(Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
+/// What a click on a confirmation link led to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    EmailChanged,
+    ListJoined,
+    Expired,
+    UnknownToken,
+}
+
+impl ConfirmationOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfirmationOutcome::Confirmed => "confirmed",
+            ConfirmationOutcome::AlreadyConfirmed => "already_confirmed",
+            ConfirmationOutcome::EmailChanged => "email_changed",
+            ConfirmationOutcome::ListJoined => "list_joined",
+            ConfirmationOutcome::Expired => "expired",
+            ConfirmationOutcome::UnknownToken => "unknown_token",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmationOutcome::Confirmed
+            | ConfirmationOutcome::AlreadyConfirmed
+            | ConfirmationOutcome::EmailChanged
+            | ConfirmationOutcome::ListJoined => StatusCode::OK,
+            ConfirmationOutcome::Expired => StatusCode::GONE,
+            ConfirmationOutcome::UnknownToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct TokenRecord {
+    subscriber_id: Uuid,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+    status: String,
+    email: String,
+    name: String,
+    new_email: Option<String>,
+    list_id: Option<Uuid>,
+}
+
 #[debug_handler]
 pub async fn confirm(
     State(state): State<AppState>,
+    metadata: ConsentMetadata,
     Query(params): Query<Parameters>,
-) -> Result<Response, StatusCode> {
-    let id = match get_subscriber_id_from_token(&state.db_pool, &params.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+) -> Result<Response> {
+    let is_expired = |token: &TokenRecord| {
+        token.created_at + state.subscriptions.confirmation_token_ttl() < Utc::now()
     };
-    match id {
-        Some(subscriber_id) => {
-            if confirm_subscriber(&state.db_pool, subscriber_id)
-                .await
-                .is_err()
-            {
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut transaction = state.db_pool.begin().await?;
+    let mut confirmed = None;
+    let outcome = match get_token(&mut transaction, &params.subscription_token).await? {
+        None => ConfirmationOutcome::UnknownToken,
+        Some(token) if token.consumed_at.is_some() => ConfirmationOutcome::AlreadyConfirmed,
+        // Only active subscriptions can move to a new address.
+        Some(token) if token.new_email.is_some() && token.status != "confirmed" => {
+            ConfirmationOutcome::UnknownToken
+        }
+        Some(token) if token.new_email.is_some() && is_expired(&token) => {
+            ConfirmationOutcome::Expired
+        }
+        Some(TokenRecord {
+            subscriber_id,
+            email,
+            new_email: Some(new_email),
+            ..
+        }) => {
+            if change_email(&mut transaction, subscriber_id, &email, &new_email).await? {
+                record_consent(
+                    &mut transaction,
+                    &[subscriber_id],
+                    ConsentEventType::Confirmation,
+                    "email_change",
+                    &metadata,
+                )
+                .await?;
+                ConfirmationOutcome::EmailChanged
+            } else {
+                ConfirmationOutcome::UnknownToken
+            }
+        }
+        // Only active subscriptions can join more lists.
+        Some(token) if token.list_id.is_some() && token.status != "confirmed" => {
+            ConfirmationOutcome::UnknownToken
+        }
+        Some(token) if token.list_id.is_some() && is_expired(&token) => {
+            ConfirmationOutcome::Expired
+        }
+        Some(TokenRecord {
+            subscriber_id,
+            list_id: Some(list_id),
+            ..
+        }) => {
+            join_list(&mut transaction, subscriber_id, list_id).await?;
+            consume_token(&mut transaction, &params.subscription_token).await?;
+            record_consent(
+                &mut transaction,
+                &[subscriber_id],
+                ConsentEventType::Confirmation,
+                "list_join",
+                &metadata,
+            )
+            .await?;
+            ConfirmationOutcome::ListJoined
+        }
+        Some(token) if token.status == "confirmed" => ConfirmationOutcome::AlreadyConfirmed,
+        // Unsubscribed and suppressed addresses must sign up again.
+        Some(token) if token.status != "pending_confirmation" => ConfirmationOutcome::UnknownToken,
+        Some(token) if is_expired(&token) => ConfirmationOutcome::Expired,
+        Some(token) => {
+            if confirm_subscriber(&mut transaction, token.subscriber_id).await? {
+                record_consent(
+                    &mut transaction,
+                    &[token.subscriber_id],
+                    ConsentEventType::Confirmation,
+                    "confirmation_link",
+                    &metadata,
+                )
+                .await?;
+                confirmed = Some(token);
+                ConfirmationOutcome::Confirmed
+            } else {
+                ConfirmationOutcome::UnknownToken
             }
-            Ok((StatusCode::OK).into_response())
         }
-        None => Err(StatusCode::UNAUTHORIZED),
+    };
+    transaction.commit().await?;
+
+    if let Some(token) = confirmed {
+        // The subscription is confirmed either way, so a failed welcome email
+        // is only logged.
+        if let Err(e) = send_welcome_email(&state, &token).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the welcome email",
+            );
+        }
+    }
+    format::render().status(outcome.status_code()).view(
+        &state.tera_engine,
+        "subscriptions/confirm.html",
+        json!({"outcome": outcome.as_str()}),
+    )
+}
+
+/// Confirms a subscriber still pending confirmation and consumes all of their
+/// outstanding tokens, so links from earlier confirmation emails can't be
+/// replayed. Returns `false`, changing nothing, for any other status: an
+/// unsubscribed or suppressed address must sign up again.
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool> {
+    let query = sqlx::query(
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+    )
+    .bind(subscriber_id);
+    if transaction.execute(query).await?.rows_affected() == 0 {
+        return Ok(false);
     }
+    consume_tokens(transaction, subscriber_id).await?;
+    Ok(true)
 }
 
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query(
+/// Moves a subscriber, and the issues still queued for them, to the address
+/// they confirmed. Returns `false` if someone else took it in the meantime.
+async fn change_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+    new_email: &str,
+) -> Result<bool> {
+    let query = sqlx::query(
         r#"
-            UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
+        UPDATE subscriptions
+        SET email = $2
+        WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM subscriptions WHERE email = $2)
         "#,
     )
     .bind(subscriber_id)
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+    .bind(new_email);
+    if transaction.execute(query).await?.rows_affected() == 0 {
+        return Ok(false);
+    }
+    let query = sqlx::query(
+        r#"
+        UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1
+        "#,
+    )
+    .bind(email)
+    .bind(new_email);
+    transaction.execute(query).await?;
+    consume_tokens(transaction, subscriber_id).await?;
+    Ok(true)
+}
+
+async fn consume_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        UPDATE subscription_tokens
+        SET consumed_at = now()
+        WHERE subscriber_id = $1 AND consumed_at IS NULL
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Consumes a single token, leaving the subscriber's other pending requests
+/// usable.
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        UPDATE subscription_tokens SET consumed_at = now() WHERE subscription_token = $1
+        "#,
+    )
+    .bind(subscription_token);
+    transaction.execute(query).await?;
     Ok(())
 }
 
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq)]
-pub struct SubscriberId(Uuid);
+async fn send_welcome_email(state: &AppState, token: &TokenRecord) -> Result<()> {
+    let unsubscribe_link =
+        unsubscribe_link(&state.base_url, &state.hmac_secret, token.subscriber_id);
+    let preferences_link =
+        preferences_link(&state.base_url, &state.hmac_secret, token.subscriber_id);
+    let body = state.tera_engine.render_email(
+        "welcome",
+        json!({
+            "name": token.name,
+            "unsubscribe_link": unsubscribe_link,
+            "preferences_link": preferences_link,
+        }),
+    )?;
+    state
+        .email_client
+        .send_email(
+            SubscriberEmail::parse(token.email.clone())?,
+            "Your subscription is confirmed",
+            &body.html,
+            &body.text,
+            Some(&unsubscribe_link),
+        )
+        .await?;
+    Ok(())
+}
 
-async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result: Option<SubscriberId> = sqlx::query_as(
+) -> Result<Option<TokenRecord>> {
+    // Locking the token serialises concurrent clicks on the same link.
+    let token = sqlx::query_as(
         r#"
-        SELECT subscriber_id FROM subscription_tokens WHERE subscription_token=$1
+        SELECT
+            t.subscriber_id,
+            t.created_at,
+            t.consumed_at,
+            t.new_email,
+            t.list_id,
+            s.status,
+            s.email,
+            s.name
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE OF t
         "#,
     )
     .bind(subscription_token)
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.0))
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(token)
 }
@@ -0,0 +1,368 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{prelude::FromRow, Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{SignedToken, TokenPurpose},
+    errors::Error,
+    startup::AppState,
+    Result,
+};
+
+use super::format;
+
+#[derive(FromRow, Serialize)]
+struct SubscriptionData {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    delivery_format: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct ListMembershipData {
+    name: String,
+    joined_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct SubscriptionTokenData {
+    subscription_token: String,
+    new_email: Option<String>,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow, Serialize)]
+struct PendingDeliveryData {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_retries: i16,
+    execute_after: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct DeliveryOutcomeData {
+    newsletter_issue_id: Uuid,
+    title: String,
+    outcome: String,
+    recorded_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct DeliveryFailureData {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct ConsentEventData {
+    email: String,
+    event_type: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+struct EmailEventData {
+    record_type: String,
+    event_type: Option<String>,
+    payload: serde_json::Value,
+    received_at: DateTime<Utc>,
+}
+
+/// Everything stored about one subscriber.
+#[derive(Serialize)]
+struct SubscriberData {
+    subscription: SubscriptionData,
+    lists: Vec<ListMembershipData>,
+    consent_events: Vec<ConsentEventData>,
+    subscription_tokens: Vec<SubscriptionTokenData>,
+    pending_deliveries: Vec<PendingDeliveryData>,
+    delivery_outcomes: Vec<DeliveryOutcomeData>,
+    delivery_failures: Vec<DeliveryFailureData>,
+    email_events: Vec<EmailEventData>,
+}
+
+/// The query string of the emailed data links, which expire.
+#[derive(Deserialize)]
+pub struct DataLinkParameters {
+    subscriber_id: Uuid,
+    issued_at: i64,
+    token: String,
+}
+
+impl DataLinkParameters {
+    fn verify(&self, state: &AppState, purpose: TokenPurpose) -> Result<()> {
+        SignedToken::parse(self.token.clone())?.verify_issued_at(
+            &state.hmac_secret,
+            purpose,
+            self.subscriber_id,
+            self.issued_at,
+            state.subscriptions.data_link_ttl(),
+            Utc::now(),
+        )
+    }
+}
+
+/// Links to download and to erase the subscriber's data, issued at `now`.
+pub fn data_links(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+    now: DateTime<Utc>,
+) -> (String, String) {
+    let link = |path: &str, purpose: TokenPurpose| {
+        let issued_at = now.timestamp();
+        let token = SignedToken::sign_issued_at(hmac_secret, purpose, subscriber_id, issued_at);
+        format!(
+            "{}{}?subscriber_id={}&issued_at={}&token={}",
+            base_url,
+            path,
+            subscriber_id,
+            issued_at,
+            token.as_ref()
+        )
+    };
+    (
+        link("/subscriptions/data", TokenPurpose::Export),
+        link("/subscriptions/data/erase", TokenPurpose::Erase),
+    )
+}
+
+/// Downloads, as JSON, every row we keep about the subscriber the link was
+/// signed for.
+#[debug_handler]
+pub async fn export_subscriber_data(
+    State(state): State<AppState>,
+    Query(params): Query<DataLinkParameters>,
+) -> Result<Response> {
+    params.verify(&state, TokenPurpose::Export)?;
+    let data = get_subscriber_data(&state.db_pool, params.subscriber_id).await?;
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"subscriber-data.json\"",
+        )],
+        Json(data),
+    )
+        .into_response())
+}
+
+/// Asks before erasing, so following the emailed link, or a mail scanner
+/// fetching it, erases nothing by itself.
+#[debug_handler]
+pub async fn erase_subscriber_data_form(
+    State(state): State<AppState>,
+    Query(params): Query<DataLinkParameters>,
+) -> Result<Response> {
+    params.verify(&state, TokenPurpose::Erase)?;
+    let erase_action = format!(
+        "{}/subscriptions/data/erase?subscriber_id={}&issued_at={}&token={}",
+        state.base_url, params.subscriber_id, params.issued_at, params.token
+    );
+    format::render().view(
+        &state.tera_engine,
+        "subscriptions/erase.html",
+        json!({"erase_action": erase_action}),
+    )
+}
+
+/// Removes the subscriber for good. Delivery statistics are kept, but no
+/// longer point to the address.
+#[debug_handler]
+pub async fn erase_subscriber_data(
+    State(state): State<AppState>,
+    Query(params): Query<DataLinkParameters>,
+) -> Result<Response> {
+    params.verify(&state, TokenPurpose::Erase)?;
+    erase_subscriber(&state.db_pool, params.subscriber_id).await?;
+    format::render().view(&state.tera_engine, "subscriptions/erased.html", json!({}))
+}
+
+async fn get_subscriber_data(pool: &PgPool, subscriber_id: Uuid) -> Result<SubscriberData> {
+    let subscription: SubscriptionData = sqlx::query_as(
+        r#"
+        SELECT id, email, name, status, delivery_format, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(Error::NotFound)?;
+    let lists = sqlx::query_as(
+        r#"
+        SELECT l.name, m.joined_at
+        FROM list_memberships m
+        JOIN mailing_lists l USING (list_id)
+        WHERE m.subscriber_id = $1
+        ORDER BY m.joined_at
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_all(pool)
+    .await?;
+    let consent_events = sqlx::query_as(
+        r#"
+        SELECT email, event_type, source, ip_address, user_agent, request_id, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_all(pool)
+    .await?;
+    let subscription_tokens = sqlx::query_as(
+        r#"
+        SELECT subscription_token, new_email, created_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_all(pool)
+    .await?;
+    let pending_deliveries = sqlx::query_as(
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE q.subscriber_email = $1
+        ORDER BY q.execute_after
+        "#,
+    )
+    .bind(&subscription.email)
+    .fetch_all(pool)
+    .await?;
+    let delivery_outcomes = sqlx::query_as(
+        r#"
+        SELECT o.newsletter_issue_id, i.title, o.outcome, o.recorded_at
+        FROM issue_delivery_outcomes o
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE o.subscriber_email = $1
+        ORDER BY o.recorded_at
+        "#,
+    )
+    .bind(&subscription.email)
+    .fetch_all(pool)
+    .await?;
+    let delivery_failures = sqlx::query_as(
+        r#"
+        SELECT f.newsletter_issue_id, i.title, f.n_retries, f.last_error, f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE f.subscriber_email = $1
+        ORDER BY f.failed_at
+        "#,
+    )
+    .bind(&subscription.email)
+    .fetch_all(pool)
+    .await?;
+    let email_events = sqlx::query_as(
+        r#"
+        SELECT record_type, event_type, payload, received_at
+        FROM email_events
+        WHERE email = $1
+        ORDER BY received_at
+        "#,
+    )
+    .bind(&subscription.email)
+    .fetch_all(pool)
+    .await?;
+    Ok(SubscriberData {
+        subscription,
+        lists,
+        consent_events,
+        subscription_tokens,
+        pending_deliveries,
+        delivery_outcomes,
+        delivery_failures,
+        email_events,
+    })
+}
+
+pub async fn erase_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    let (email,): (String,) = sqlx::query_as(
+        r#"
+        SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    // The queue goes first: a worker holding one of its rows has to finish
+    // recording the outcome before the outcomes are anonymized.
+    let query = sqlx::query(
+        r#"
+        DELETE FROM issue_delivery_queue WHERE subscriber_email = $1
+        "#,
+    )
+    .bind(&email);
+    transaction.execute(query).await?;
+    let erased_email = format!("erased-{}", Uuid::new_v4());
+    let query = sqlx::query(
+        r#"
+        UPDATE issue_delivery_outcomes SET subscriber_email = $2 WHERE subscriber_email = $1
+        "#,
+    )
+    .bind(&email)
+    .bind(&erased_email);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE issue_delivery_failures SET subscriber_email = $2 WHERE subscriber_email = $1
+        "#,
+    )
+    .bind(&email)
+    .bind(&erased_email);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM email_events WHERE email = $1
+        "#,
+    )
+    .bind(&email);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM subscription_tokens WHERE subscriber_id = $1
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    // List memberships and consent events go with the subscription.
+    let query = sqlx::query(
+        r#"
+        DELETE FROM subscriptions WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
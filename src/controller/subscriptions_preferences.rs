@@ -0,0 +1,337 @@
+use axum::{
+    debug_handler,
+    extract::{Form, Query, State},
+    response::Response,
+};
+use chrono::Utc;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{prelude::FromRow, Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DeliveryFormat, SignedToken, SubscriberEmail, SubscriberName, TokenPurpose},
+    errors::Error,
+    startup::AppState,
+    Result,
+};
+
+use super::{
+    check_signup_rate, data_links, format, generate_subscription_token, unsubscribe_link,
+    ConsentMetadata,
+};
+
+#[derive(Deserialize)]
+pub struct PreferencesParameters {
+    pub subscriber_id: Uuid,
+    token: String,
+}
+
+impl PreferencesParameters {
+    pub fn verify(&self, hmac_secret: &Secret<String>) -> Result<()> {
+        SignedToken::parse(self.token.clone())?.verify(
+            hmac_secret,
+            TokenPurpose::Preferences,
+            self.subscriber_id,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreferencesFormData {
+    name: String,
+    #[serde(default)]
+    delivery_format: DeliveryFormat,
+}
+
+#[derive(Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+#[derive(FromRow, Serialize)]
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    delivery_format: String,
+}
+
+pub fn preferences_link(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> String {
+    signed_link(
+        base_url,
+        "/subscriptions/preferences",
+        hmac_secret,
+        subscriber_id,
+    )
+}
+
+fn signed_link(
+    base_url: &str,
+    path: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> String {
+    let token = SignedToken::sign(hmac_secret, TokenPurpose::Preferences, subscriber_id);
+    format!(
+        "{}{}?subscriber_id={}&token={}",
+        base_url,
+        path,
+        subscriber_id,
+        token.as_ref()
+    )
+}
+
+#[debug_handler]
+pub async fn preferences_form(
+    State(state): State<AppState>,
+    Query(params): Query<PreferencesParameters>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    let subscriber = get_subscriber(&state.db_pool, params.subscriber_id).await?;
+    render_preferences(&state, params.subscriber_id, subscriber, None)
+}
+
+#[debug_handler]
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    Query(params): Query<PreferencesParameters>,
+    Form(form): Form<PreferencesFormData>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    let subscriber = get_active_subscriber(&state.db_pool, params.subscriber_id).await?;
+    let name = SubscriberName::parse(form.name)?;
+    let query = sqlx::query(
+        r#"
+        UPDATE subscriptions SET name = $2, delivery_format = $3 WHERE id = $1
+        "#,
+    )
+    .bind(params.subscriber_id)
+    .bind(name.as_ref())
+    .bind(form.delivery_format.as_str());
+    state.db_pool.execute(query).await?;
+    let subscriber = Subscriber {
+        name: name.as_ref().to_string(),
+        delivery_format: form.delivery_format.as_str().to_string(),
+        ..subscriber
+    };
+    render_preferences(
+        &state,
+        params.subscriber_id,
+        subscriber,
+        Some("Your preferences have been saved.".to_string()),
+    )
+}
+
+/// Sends a confirmation link to the new address; the subscription only moves
+/// once it is followed. Rate limited like signups, since the caller picks the
+/// address.
+#[debug_handler]
+pub async fn change_subscriber_email(
+    State(state): State<AppState>,
+    metadata: ConsentMetadata,
+    Query(params): Query<PreferencesParameters>,
+    Form(form): Form<EmailFormData>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    let subscriber = get_active_subscriber(&state.db_pool, params.subscriber_id).await?;
+    let new_email = SubscriberEmail::parse(form.email)?;
+    if new_email.as_ref() == subscriber.email {
+        return render_preferences(
+            &state,
+            params.subscriber_id,
+            subscriber,
+            Some("That is already your email address.".to_string()),
+        );
+    }
+    // Each request emails an address of the caller's choosing, so it shares
+    // the signup limits.
+    check_signup_rate(
+        &state.db_pool,
+        &state.subscriptions,
+        metadata.ip_address.as_deref(),
+        new_email.as_ref(),
+    )
+    .await?;
+    let notice = format!(
+        "We've sent a confirmation link to {}. Follow it to start receiving our newsletter there.",
+        new_email.as_ref()
+    );
+
+    // The page reads the same whether or not the address is taken, so it
+    // doesn't reveal who else is subscribed.
+    if !email_is_registered(&state.db_pool, new_email.as_ref()).await? {
+        let subscription_token = generate_subscription_token();
+        store_email_change_token(
+            &state.db_pool,
+            params.subscriber_id,
+            &subscription_token,
+            new_email.as_ref(),
+        )
+        .await?;
+
+        let confirmation_link = format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            state.base_url, subscription_token
+        );
+        let body = state.tera_engine.render_email(
+            "email_change",
+            json!({"name": subscriber.name, "confirmation_link": confirmation_link}),
+        )?;
+        state
+            .email_client
+            .send_email(
+                new_email,
+                "Confirm your new email address",
+                &body.html,
+                &body.text,
+                None,
+            )
+            .await?;
+    }
+    render_preferences(&state, params.subscriber_id, subscriber, Some(notice))
+}
+
+/// Emails the subscriber short-lived links to download or erase their data.
+/// The preferences link can't do either itself: it never expires and every
+/// welcome email carries it, so it may have been forwarded.
+#[debug_handler]
+pub async fn request_data_links(
+    State(state): State<AppState>,
+    metadata: ConsentMetadata,
+    Query(params): Query<PreferencesParameters>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    let subscriber = get_subscriber(&state.db_pool, params.subscriber_id).await?;
+    check_signup_rate(
+        &state.db_pool,
+        &state.subscriptions,
+        metadata.ip_address.as_deref(),
+        &subscriber.email,
+    )
+    .await?;
+    let (export_link, erase_link) = data_links(
+        &state.base_url,
+        &state.hmac_secret,
+        params.subscriber_id,
+        Utc::now(),
+    );
+    let body = state.tera_engine.render_email(
+        "data_links",
+        json!({
+            "name": subscriber.name,
+            "export_link": export_link,
+            "erase_link": erase_link,
+            "ttl_minutes": state.subscriptions.data_link_ttl_minutes,
+        }),
+    )?;
+    state
+        .email_client
+        .send_email(
+            SubscriberEmail::parse(subscriber.email.clone())?,
+            "Your data",
+            &body.html,
+            &body.text,
+            None,
+        )
+        .await?;
+    let notice = format!(
+        "We've emailed the links to {}. They work for {} minutes.",
+        subscriber.email, state.subscriptions.data_link_ttl_minutes
+    );
+    render_preferences(&state, params.subscriber_id, subscriber, Some(notice))
+}
+
+fn render_preferences(
+    state: &AppState,
+    subscriber_id: Uuid,
+    subscriber: Subscriber,
+    notice: Option<String>,
+) -> Result<Response> {
+    let action = preferences_link(&state.base_url, &state.hmac_secret, subscriber_id);
+    let email_action = signed_link(
+        &state.base_url,
+        "/subscriptions/preferences/email",
+        &state.hmac_secret,
+        subscriber_id,
+    );
+    let unsubscribe_action = unsubscribe_link(&state.base_url, &state.hmac_secret, subscriber_id);
+    let data_action = signed_link(
+        &state.base_url,
+        "/subscriptions/data",
+        &state.hmac_secret,
+        subscriber_id,
+    );
+    format::render().view(
+        &state.tera_engine,
+        "subscriptions/preferences.html",
+        json!({
+            "subscriber": subscriber,
+            "notice": notice,
+            "action": action,
+            "email_action": email_action,
+            "unsubscribe_action": unsubscribe_action,
+            "data_action": data_action,
+        }),
+    )
+}
+
+async fn get_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<Subscriber> {
+    sqlx::query_as(
+        r#"
+        SELECT email, name, status, delivery_format FROM subscriptions WHERE id = $1
+        "#,
+    )
+    .bind(subscriber_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(Error::NotFound)
+}
+
+/// Unsubscribed and suppressed addresses have to sign up again before they
+/// can change anything.
+async fn get_active_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<Subscriber> {
+    let subscriber = get_subscriber(pool, subscriber_id).await?;
+    if subscriber.status != "confirmed" {
+        return Err(Error::BadRequest(
+            "Only active subscriptions can be changed.".into(),
+        ));
+    }
+    Ok(subscriber)
+}
+
+async fn email_is_registered(pool: &PgPool, email: &str) -> Result<bool> {
+    let row: Option<(Uuid,)> = sqlx::query_as(
+        r#"
+        SELECT id FROM subscriptions WHERE email = $1
+        "#,
+    )
+    .bind(email)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+async fn store_email_change_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+    new_email: &str,
+) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, new_email)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(subscription_token)
+    .bind(subscriber_id)
+    .bind(new_email);
+    pool.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use axum::{debug_handler, extract::State, response::Response};
+use chrono::Utc;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::SubscriptionSettings, domain::ProofOfWorkChallenge, errors::Error,
+    startup::AppState, Result,
+};
+
+use super::format;
+
+/// Hands out a proof of work challenge for the next signup.
+#[debug_handler]
+pub async fn subscription_challenge(State(state): State<AppState>) -> Result<Response> {
+    let challenge = ProofOfWorkChallenge::issue(Utc::now()).sign(&state.hmac_secret);
+    format::json(json!({
+        "challenge": challenge,
+        "difficulty": state.subscriptions.proof_of_work_difficulty,
+    }))
+}
+
+/// Checks the proof of work for a signup of `email`, returning its challenge
+/// to redeem with [`redeem_proof_of_work`], or `None` when proof of work is
+/// turned off.
+pub fn verify_proof_of_work(
+    state: &AppState,
+    challenge: Option<&str>,
+    email: &str,
+    nonce: Option<&str>,
+) -> Result<Option<ProofOfWorkChallenge>> {
+    let difficulty = state.subscriptions.proof_of_work_difficulty;
+    if difficulty == 0 {
+        return Ok(None);
+    }
+    let (Some(challenge), Some(nonce)) = (challenge, nonce) else {
+        return Err(Error::BadRequest("A proof of work is required.".into()));
+    };
+    let challenge = ProofOfWorkChallenge::verify(
+        &state.hmac_secret,
+        challenge,
+        email,
+        nonce,
+        difficulty,
+        state.subscriptions.proof_of_work_ttl(),
+        Utc::now(),
+    )?;
+    Ok(Some(challenge))
+}
+
+/// Records the challenge as used, so it can't be replayed.
+pub async fn redeem_proof_of_work(pool: &PgPool, challenge: &ProofOfWorkChallenge) -> Result<()> {
+    let redeemed = sqlx::query(
+        r#"
+        INSERT INTO proof_of_work_redemptions (salt, redeemed_at)
+        VALUES ($1, now())
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(challenge.salt())
+    .execute(pool)
+    .await?;
+    if redeemed.rows_affected() == 0 {
+        return Err(Error::BadRequest(
+            "The proof of work challenge was already used.".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses the signup if the client, or the address it signs up, already
+/// reached its hourly limit, and counts it otherwise. Limiting the address
+/// keeps anyone from flooding a stranger's inbox with confirmation emails.
+pub async fn check_signup_rate(
+    pool: &PgPool,
+    settings: &SubscriptionSettings,
+    ip_address: Option<&str>,
+    email: &str,
+) -> Result<()> {
+    let email = email.to_lowercase();
+    let (n_from_ip, n_for_email): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT
+            count(*) FILTER (WHERE ip_address = $1),
+            count(*) FILTER (WHERE email = $2)
+        FROM signup_attempts
+        WHERE attempted_at > now() - interval '1 hour'
+            AND (ip_address = $1 OR email = $2)
+        "#,
+    )
+    .bind(ip_address)
+    .bind(&email)
+    .fetch_one(pool)
+    .await?;
+    if n_from_ip >= i64::from(settings.max_signups_per_ip_per_hour) {
+        return Err(Error::TooManyRequests(format!(
+            "Too many signups from {}.",
+            ip_address.unwrap_or_default()
+        )));
+    }
+    if n_for_email >= i64::from(settings.max_signups_per_email_per_hour) {
+        return Err(Error::TooManyRequests(format!(
+            "Too many signups for {}.",
+            email
+        )));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO signup_attempts (ip_address, email, attempted_at)
+        VALUES ($1, $2, now())
+        "#,
+    )
+    .bind(ip_address)
+    .bind(&email)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    response::Response,
+};
+use secrecy::Secret;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{SignedToken, TokenPurpose},
+    startup::AppState,
+    Result,
+};
+
+use super::{format, preferences_link};
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+impl UnsubscribeParameters {
+    fn verify(&self, hmac_secret: &Secret<String>) -> Result<()> {
+        SignedToken::parse(self.token.clone())?.verify(
+            hmac_secret,
+            TokenPurpose::Unsubscribe,
+            self.subscriber_id,
+        )
+    }
+}
+
+pub fn unsubscribe_link(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> String {
+    let token = SignedToken::sign(hmac_secret, TokenPurpose::Unsubscribe, subscriber_id);
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        base_url,
+        subscriber_id,
+        token.as_ref()
+    )
+}
+
+#[debug_handler]
+pub async fn unsubscribe_form(
+    State(state): State<AppState>,
+    Query(params): Query<UnsubscribeParameters>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    let action = unsubscribe_link(&state.base_url, &state.hmac_secret, params.subscriber_id);
+    let preferences = preferences_link(&state.base_url, &state.hmac_secret, params.subscriber_id);
+    format::render().view(
+        &state.tera_engine,
+        "subscriptions/unsubscribe.html",
+        json!({"action": action, "preferences": preferences, "unsubscribed": false}),
+    )
+}
+
+/// Handles both the form on the unsubscribe page and RFC 8058 one-click
+/// requests sent by mail clients, which POST to the `List-Unsubscribe` URL.
+#[debug_handler]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(params): Query<UnsubscribeParameters>,
+) -> Result<Response> {
+    params.verify(&state.hmac_secret)?;
+    unsubscribe_subscriber(&state.db_pool, params.subscriber_id).await?;
+    format::render().view(
+        &state.tera_engine,
+        "subscriptions/unsubscribe.html",
+        json!({"unsubscribed": true}),
+    )
+}
+
+/// Unsubscribes the subscriber. Suppressed addresses stay suppressed, since an
+/// unsubscribed one can sign up again.
+pub async fn unsubscribe_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    let query = sqlx::query(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1 AND status <> 'suppressed'
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    let query = sqlx::query(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = (SELECT email FROM subscriptions WHERE id = $1)
+        "#,
+    )
+    .bind(subscriber_id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use axum::{debug_handler, extract::State, response::Response, Json};
+use axum_extra::{
+    headers::{authorization::Basic, Authorization},
+    TypedHeader,
+};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{configuration::WebhookSettings, errors::Error, startup::AppState, Result};
+
+use super::format;
+
+/// The fields we use from Postmark's bounce and spam complaint webhooks;
+/// the full payload is stored alongside.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    event_type: Option<String>,
+    email: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+impl PostmarkEvent {
+    /// Hard bounces and spam complaints mean we must stop mailing the address.
+    fn suppresses_address(&self) -> bool {
+        match self.record_type.as_str() {
+            "SpamComplaint" => true,
+            "Bounce" => self.event_type.as_deref() == Some("HardBounce"),
+            _ => false,
+        }
+    }
+}
+
+#[debug_handler]
+pub async fn postmark_webhook(
+    State(state): State<AppState>,
+    authorization: Option<TypedHeader<Authorization<Basic>>>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Response> {
+    verify_webhook_credentials(&state.webhook, authorization)?;
+    let event: PostmarkEvent = serde_json::from_value(payload.clone())
+        .map_err(|e| Error::BadRequest(format!("Invalid Postmark event: {}", e)))?;
+    record_postmark_event(&state.db_pool, &event, &payload).await?;
+    format::empty()
+}
+
+fn verify_webhook_credentials(
+    settings: &WebhookSettings,
+    authorization: Option<TypedHeader<Authorization<Basic>>>,
+) -> Result<()> {
+    let Some(TypedHeader(Authorization(credentials))) = authorization else {
+        return Err(Error::Unauthorized(
+            "Missing webhook credentials.".to_string(),
+        ));
+    };
+    // Comparing digests keeps the comparison time independent of the secret.
+    let password_matches = Sha256::digest(credentials.password().as_bytes())
+        == Sha256::digest(settings.password.expose_secret().as_bytes());
+    if credentials.username() == settings.username && password_matches {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized(
+            "Invalid webhook credentials.".to_string(),
+        ))
+    }
+}
+
+async fn record_postmark_event(
+    pool: &PgPool,
+    event: &PostmarkEvent,
+    payload: &serde_json::Value,
+) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    let query = sqlx::query(
+        r#"
+        INSERT INTO email_events (
+            email_event_id,
+            provider,
+            record_type,
+            event_type,
+            email,
+            provider_message_id,
+            payload,
+            received_at
+        )
+        VALUES ($1, 'postmark', $2, $3, $4, $5, $6, now())
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(&event.record_type)
+    .bind(&event.event_type)
+    .bind(&event.email)
+    .bind(&event.message_id)
+    .bind(payload);
+    transaction.execute(query).await?;
+
+    if event.suppresses_address() {
+        // Postmark may not keep the case the address was stored with.
+        let suppressed: Vec<(Uuid, String)> = sqlx::query_as(
+            r#"
+            UPDATE subscriptions
+            SET status = 'suppressed'
+            WHERE lower(email) = lower($1)
+            RETURNING id, email
+            "#,
+        )
+        .bind(&event.email)
+        .fetch_all(&mut *transaction)
+        .await?;
+        for (subscriber_id, email) in suppressed {
+            tracing::info!(
+                %subscriber_id,
+                "Suppressing a subscriber after a {} event",
+                event.record_type
+            );
+            let query = sqlx::query(
+                r#"
+                DELETE FROM issue_delivery_queue WHERE subscriber_email = $1
+                "#,
+            )
+            .bind(email);
+            transaction.execute(query).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
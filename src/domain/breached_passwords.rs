@@ -0,0 +1,110 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+/// Length of the hash prefix Have I Been Pwned groups breached hashes by for
+/// k-anonymity range lookups.
+const RANGE_PREFIX_LEN: usize = 5;
+
+/// Looks a password up in a local copy of the Pwned Passwords list.
+///
+/// The file holds one `SHA1:COUNT` line per breached password, with upper case
+/// hashes sorted like the Pwned Passwords downloader writes them. Only the
+/// range of hashes sharing the password's 5 character prefix is read, which is
+/// found by binary search, so the file can be as large as the full list.
+pub fn is_breached_password(path: &Path, password: &str) -> std::io::Result<bool> {
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LEN);
+    let mut file = File::open(path)?;
+    let (mut low, mut high) = (0, file.metadata()?.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let mut line = String::new();
+        lines_from(&mut file, middle)?.read_line(&mut line)?;
+        if !line.is_empty() && line.as_str() < prefix {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    for line in lines_from(&mut file, low)?.lines() {
+        let line = line?;
+        let Some(range_suffix) = line.strip_prefix(prefix) else {
+            break;
+        };
+        let range_suffix = range_suffix.split(':').next().unwrap_or_default();
+        match range_suffix.cmp(suffix) {
+            Ordering::Less => continue,
+            Ordering::Equal => return Ok(true),
+            Ordering::Greater => break,
+        }
+    }
+    Ok(false)
+}
+
+/// A reader positioned at the first whole line starting at or after `offset`.
+fn lines_from(file: &mut File, offset: u64) -> std::io::Result<BufReader<&mut File>> {
+    file.seek(SeekFrom::Start(offset.saturating_sub(1)))?;
+    let mut reader = BufReader::new(file);
+    if offset > 0 {
+        reader.read_line(&mut String::new())?;
+    }
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use sha1::{Digest, Sha1};
+    use uuid::Uuid;
+
+    use super::is_breached_password;
+
+    fn breach_file(passwords: &[&str]) -> PathBuf {
+        let mut lines = passwords
+            .iter()
+            .map(|password| format!("{}:42", hex::encode_upper(Sha1::digest(password))))
+            .collect::<Vec<_>>();
+        lines.sort();
+        let path = std::env::temp_dir().join(format!("breached-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, lines.join("\r\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn every_listed_password_is_found() {
+        let passwords = (0..200).map(|i| format!("password{i}")).collect::<Vec<_>>();
+        let passwords = passwords.iter().map(String::as_str).collect::<Vec<_>>();
+        let path = breach_file(&passwords);
+
+        for password in passwords {
+            assert!(is_breached_password(&path, password).unwrap(), "{password}");
+        }
+    }
+
+    #[test]
+    fn unlisted_passwords_are_not_found() {
+        let path = breach_file(&["password", "123456", "qwerty"]);
+
+        for password in ["a wizard of earthsea", "Password", ""] {
+            assert!(
+                !is_breached_password(&path, password).unwrap(),
+                "{password}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_missing_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!("breached-{}.txt", Uuid::new_v4()));
+
+        assert!(is_breached_password(&path, "password").is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use serde::Deserialize;
+
+/// How a subscriber wants to receive newsletter issues.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryFormat {
+    #[default]
+    Html,
+    /// Only the plain text body is sent.
+    Text,
+}
+
+impl DeliveryFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFormat::Html => "html",
+            DeliveryFormat::Text => "text",
+        }
+    }
+}
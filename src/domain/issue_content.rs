@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+
+use kuchikiki::traits::TendrilSink;
+use pulldown_cmark::{html, Options, Parser};
+use serde::Deserialize;
+
+use crate::{errors::Error, Result};
+
+/// Width the derived plain text body is wrapped at.
+const TEXT_WIDTH: usize = 80;
+
+/// CSS properties kept in `style` attributes, enough for common email layouts.
+const STYLE_PROPERTIES: &[&str] = &[
+    "background",
+    "background-color",
+    "border",
+    "border-bottom",
+    "border-collapse",
+    "border-color",
+    "border-left",
+    "border-radius",
+    "border-right",
+    "border-style",
+    "border-top",
+    "border-width",
+    "color",
+    "display",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "height",
+    "letter-spacing",
+    "line-height",
+    "margin",
+    "margin-bottom",
+    "margin-left",
+    "margin-right",
+    "margin-top",
+    "max-width",
+    "padding",
+    "padding-bottom",
+    "padding-left",
+    "padding-right",
+    "padding-top",
+    "text-align",
+    "text-decoration",
+    "text-transform",
+    "vertical-align",
+    "white-space",
+    "width",
+];
+
+/// The language an issue's content is written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFormat {
+    #[default]
+    Html,
+    Markdown,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Html => "html",
+            ContentFormat::Markdown => "markdown",
+        }
+    }
+}
+
+/// What the admin wrote for an issue, along with the bodies sent to
+/// subscribers.
+#[derive(Debug)]
+pub struct IssueContent {
+    pub format: ContentFormat,
+    pub source_content: String,
+    pub source_text_content: Option<String>,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+impl IssueContent {
+    /// Converts `source_content` to HTML, inlines its `<style>` rules and
+    /// strips anything unsafe. The plain text body is derived from the result
+    /// unless `source_text_content` is given.
+    pub fn parse(
+        format: ContentFormat,
+        source_content: String,
+        source_text_content: Option<String>,
+    ) -> Result<Self> {
+        let html_content = match format {
+            ContentFormat::Html => source_content.clone(),
+            ContentFormat::Markdown => markdown_to_html(&source_content),
+        };
+        let html_content = sanitize(&inline_css(&html_content));
+        let source_text_content = source_text_content.filter(|text| !text.trim().is_empty());
+        let text_content = match &source_text_content {
+            Some(text_content) => text_content.clone(),
+            None => html2text::from_read(html_content.as_bytes(), TEXT_WIDTH)
+                .map_err(|e| Error::Message(format!("Failed to derive the plain text: {}", e)))?,
+        };
+        Ok(Self {
+            format,
+            source_content,
+            source_text_content,
+            html_content,
+            text_content,
+        })
+    }
+}
+
+fn markdown_to_html(source: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut html_content = String::new();
+    html::push_html(&mut html_content, Parser::new_ext(source, options));
+    html_content
+}
+
+/// Moves the rules of `<style>` elements onto the elements they match, since
+/// many email clients ignore stylesheets. Rules apply in source order and
+/// existing `style` attributes win; specificity isn't taken into account.
+fn inline_css(html_content: &str) -> String {
+    let document = kuchikiki::parse_html().one(html_content);
+    let Ok(styles) = document.select("style") else {
+        return html_content.to_string();
+    };
+    let mut rules = Vec::new();
+    for style in styles.collect::<Vec<_>>() {
+        rules.extend(parse_css_rules(&style.text_contents()));
+        style.as_node().detach();
+    }
+    // Prepending in reverse keeps later rules after earlier ones.
+    for (selectors, declarations) in rules.iter().rev() {
+        // Selectors we can't match, like `a:hover`, are dropped.
+        let Ok(elements) = document.select(selectors) else {
+            continue;
+        };
+        for element in elements {
+            let mut attributes = element.attributes.borrow_mut();
+            let style = match attributes.get("style") {
+                Some(existing) => format!("{}; {}", declarations, existing),
+                None => declarations.clone(),
+            };
+            attributes.insert("style", style);
+        }
+    }
+    document.to_string()
+}
+
+/// Splits a stylesheet into `(selectors, declarations)` pairs, skipping
+/// comments and at-rules such as `@media`.
+fn parse_css_rules(css: &str) -> Vec<(String, String)> {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..]
+            .find("*/")
+            .map_or(css.len(), |end| start + end + 2);
+        css.replace_range(start..end, "");
+    }
+
+    let mut rules = Vec::new();
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        let mut depth = 0;
+        let mut close = rest.len();
+        for (i, c) in rest[open..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = open + i;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let body = rest[open + 1..close].trim();
+        if !prelude.starts_with('@') && !prelude.is_empty() && !body.is_empty() {
+            rules.push((prelude.to_string(), body.trim_end_matches(';').to_string()));
+        }
+        rest = rest.get(close + 1..).unwrap_or_default();
+    }
+    rules
+}
+
+fn sanitize(html_content: &str) -> String {
+    ammonia::Builder::default()
+        .add_generic_attributes(&["style"])
+        .filter_style_properties(STYLE_PROPERTIES.iter().copied().collect::<HashSet<_>>())
+        .clean(html_content)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::issue_content::{parse_css_rules, ContentFormat, IssueContent};
+
+    fn html(source: &str) -> IssueContent {
+        IssueContent::parse(ContentFormat::Html, source.into(), None).unwrap()
+    }
+
+    #[test]
+    fn markdown_is_converted_to_html() {
+        let content = IssueContent::parse(
+            ContentFormat::Markdown,
+            "# Hello\n\nSome *news*.".into(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            content.html_content,
+            "<h1>Hello</h1>\n<p>Some <em>news</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let content = html(
+            r#"<p onclick="steal()">Hi</p><script>steal()</script><a href="javascript:steal()">x</a>"#,
+        );
+        assert!(!content.html_content.contains("script"));
+        assert!(!content.html_content.contains("onclick"));
+        assert!(!content.html_content.contains("steal"));
+        assert!(content.html_content.contains("<p>Hi</p>"));
+    }
+
+    #[test]
+    fn style_rules_are_inlined_with_inline_styles_winning() {
+        let content = html(
+            r#"<style>p { color: red } .big { font-size: 20px } p { color: blue }</style>
+            <p class="big" style="font-weight: bold">Hi</p>"#,
+        );
+        assert!(!content.html_content.contains("<style"));
+        assert!(content
+            .html_content
+            .contains(r#"<p style="color:red;font-size:20px;color:blue;font-weight:bold">Hi</p>"#));
+    }
+
+    #[test]
+    fn unsafe_style_properties_are_dropped() {
+        let content = html(r#"<p style="position: fixed; color: red">Hi</p>"#);
+        assert!(content
+            .html_content
+            .contains(r#"<p style="color:red">Hi</p>"#));
+    }
+
+    #[test]
+    fn the_plain_text_is_derived_when_omitted() {
+        let content = IssueContent::parse(
+            ContentFormat::Markdown,
+            "# Hello\n\nSome news.".into(),
+            Some("  ".into()),
+        )
+        .unwrap();
+        assert!(content.text_content.contains("Hello"));
+        assert!(content.text_content.contains("Some news."));
+        assert!(!content.text_content.contains('<'));
+        assert_eq!(content.source_text_content, None);
+    }
+
+    #[test]
+    fn a_given_plain_text_is_kept() {
+        let content = IssueContent::parse(
+            ContentFormat::Html,
+            "<p>Hello</p>".into(),
+            Some("Hand written".into()),
+        )
+        .unwrap();
+        assert_eq!(content.text_content, "Hand written");
+    }
+
+    #[test]
+    fn placeholders_survive_processing() {
+        let content = html(r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">Leave</a>"#);
+        assert!(content.html_content.contains("Hi {{ name }}"));
+        assert!(content
+            .html_content
+            .contains(r#"href="{{ unsubscribe_url }}""#));
+    }
+
+    #[test]
+    fn comments_and_at_rules_are_skipped_when_parsing_css() {
+        let rules = parse_css_rules(
+            "/* heading */ h1 { color: red; } @media (max-width: 600px) { h1 { color: blue } } p{margin:0}",
+        );
+        assert_eq!(
+            rules,
+            vec![
+                ("h1".to_string(), "color: red".to_string()),
+                ("p".to_string(), "margin:0".to_string()),
+            ]
+        );
+    }
+}
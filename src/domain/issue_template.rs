@@ -0,0 +1,181 @@
+use std::error::Error as _;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{errors::Error, Result};
+
+// The `.html` suffix turns on autoescaping for the HTML body only.
+const TITLE: &str = "title.txt";
+const HTML_CONTENT: &str = "content.html";
+const TEXT_CONTENT: &str = "content.txt";
+
+/// The variables a newsletter issue can use, e.g. `{{ name }}`.
+#[derive(Serialize)]
+pub struct Recipient {
+    pub name: Option<String>,
+    pub unsubscribe_url: Option<String>,
+    pub subscribed_at: Option<String>,
+}
+
+impl Recipient {
+    pub fn new(
+        name: Option<String>,
+        unsubscribe_url: Option<String>,
+        subscribed_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            name,
+            unsubscribe_url,
+            subscribed_at: subscribed_at.map(|at| at.format("%B %-d, %Y").to_string()),
+        }
+    }
+
+    /// Stand-in values used to check templates and for test sends.
+    pub fn example(base_url: &str) -> Self {
+        Self::new(
+            Some("Ursula Le Guin".to_string()),
+            Some(format!("{}/subscriptions/unsubscribe", base_url)),
+            Some(Utc::now()),
+        )
+    }
+}
+
+/// An issue rendered for one recipient.
+pub struct RenderedIssue {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// The title and bodies of a newsletter issue, compiled as Tera templates so
+/// they can be personalised per recipient.
+#[derive(Debug)]
+pub struct IssueTemplate(Tera);
+
+impl IssueTemplate {
+    pub fn parse(title: &str, html_content: &str, text_content: &str) -> Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (TITLE, title),
+            (HTML_CONTENT, html_content),
+            (TEXT_CONTENT, text_content),
+        ])
+        .map_err(invalid_template)?;
+        Ok(Self(tera))
+    }
+
+    /// Parses the templates and renders them for an example recipient, which
+    /// catches syntax errors as well as unknown variables.
+    pub fn validate(
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+        base_url: &str,
+    ) -> Result<()> {
+        Self::parse(title, html_content, text_content)?.render(&Recipient::example(base_url))?;
+        Ok(())
+    }
+
+    pub fn render(&self, recipient: &Recipient) -> Result<RenderedIssue> {
+        let context = Context::from_serialize(recipient)?;
+        let render = |name| self.0.render(name, &context).map_err(invalid_template);
+        Ok(RenderedIssue {
+            title: render(TITLE)?,
+            html_content: render(HTML_CONTENT)?,
+            text_content: render(TEXT_CONTENT)?,
+        })
+    }
+}
+
+/// Tera puts the useful part of its errors in the source chain.
+fn invalid_template(e: tera::Error) -> Error {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    Error::BadRequest(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::issue_template::{IssueTemplate, Recipient};
+
+    fn recipient() -> Recipient {
+        Recipient::new(
+            Some("<Ursula>".to_string()),
+            Some("https://example.com/unsubscribe?a=1&b=2".to_string()),
+            Some("2024-11-20T10:00:00Z".parse().unwrap()),
+        )
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_the_recipient_details() {
+        let template = IssueTemplate::parse(
+            "News for {{ name }}",
+            "<p>Hi {{ name }}</p>",
+            "Subscribed since {{ subscribed_at }}. Leave: {{ unsubscribe_url }}",
+        )
+        .unwrap();
+
+        let issue = template.render(&recipient()).unwrap();
+
+        assert_eq!(issue.title, "News for <Ursula>");
+        assert_eq!(issue.html_content, "<p>Hi &lt;Ursula&gt;</p>");
+        assert_eq!(
+            issue.text_content,
+            "Subscribed since November 20, 2024. Leave: https://example.com/unsubscribe?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn content_without_placeholders_is_unchanged() {
+        let template = IssueTemplate::parse("Title", "<p>Body</p>", "Body").unwrap();
+
+        let issue = template.render(&recipient()).unwrap();
+
+        assert_eq!(issue.title, "Title");
+        assert_eq!(issue.html_content, "<p>Body</p>");
+        assert_eq!(issue.text_content, "Body");
+    }
+
+    #[test]
+    fn a_missing_name_renders_as_empty() {
+        let template = IssueTemplate::parse("Title", "<p>Hi {{ name }}</p>", "Body").unwrap();
+
+        let issue = template.render(&Recipient::new(None, None, None)).unwrap();
+
+        assert_eq!(issue.html_content, "<p>Hi </p>");
+    }
+
+    #[test]
+    fn broken_syntax_is_rejected() {
+        assert_err!(IssueTemplate::parse("Title", "<p>Hi {{ name </p>", "Body"));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err!(IssueTemplate::validate(
+            "Title",
+            "<p>Hi {{ nmae }}</p>",
+            "Body",
+            "https://example.com"
+        ));
+    }
+
+    #[test]
+    fn known_variables_are_accepted() {
+        assert_ok!(IssueTemplate::validate(
+            "{{ name }}",
+            "<p>{{ unsubscribe_url }}</p>",
+            "{{ subscribed_at }}",
+            "https://example.com"
+        ));
+    }
+}
@@ -1,11 +1,27 @@
+mod breached_passwords;
 mod change_password;
+mod delivery_format;
+mod issue_content;
+mod issue_template;
 mod login;
 mod new_subscriber;
+mod proof_of_work;
+mod role;
+mod signed_token;
 mod subscriber_email;
 mod subscriber_name;
+mod totp;
 
+pub use breached_passwords::is_breached_password;
 pub use change_password::ChangePasswordForm;
+pub use delivery_format::DeliveryFormat;
+pub use issue_content::{ContentFormat, IssueContent};
+pub use issue_template::{IssueTemplate, Recipient, RenderedIssue};
 pub use login::LoginForm;
 pub use new_subscriber::NewSubscriber;
+pub use proof_of_work::ProofOfWorkChallenge;
+pub use role::Role;
+pub use signed_token::{SignedToken, TokenPurpose};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use totp::TotpSecret;
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use crate::{errors::Error, Result};
+
+/// A signed challenge a client has to solve before it may sign up: it must
+/// find a nonce for which `sha256("<challenge>:<email>:<nonce>")` starts with
+/// enough zero bits, `<email>` being the address exactly as submitted. Being
+/// signed, challenges need no server side state to verify; keeping each one
+/// to a single signup is up to the caller, using [`ProofOfWorkChallenge::salt`].
+#[derive(Debug)]
+pub struct ProofOfWorkChallenge {
+    issued_at: i64,
+    salt: String,
+}
+
+impl ProofOfWorkChallenge {
+    pub fn issue(now: DateTime<Utc>) -> Self {
+        let mut salt = [0u8; 16];
+        thread_rng().fill_bytes(&mut salt);
+        Self {
+            issued_at: now.timestamp(),
+            salt: hex::encode(salt),
+        }
+    }
+
+    /// The challenge as handed to the client, `<issued at>.<salt>.<tag>`.
+    pub fn sign(&self, secret: &Secret<String>) -> String {
+        let tag = self.mac(secret).finalize().into_bytes();
+        format!("{}.{}.{}", self.issued_at, self.salt, hex::encode(tag))
+    }
+
+    /// Checks that the challenge was issued by us less than `max_age` ago
+    /// and that `nonce` solves it for `email`.
+    pub fn verify(
+        secret: &Secret<String>,
+        challenge: &str,
+        email: &str,
+        nonce: &str,
+        difficulty: u8,
+        max_age: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let invalid = || Error::BadRequest("Invalid proof of work.".to_string());
+        let mut parts = challenge.split('.');
+        let (Some(issued_at), Some(salt), Some(tag), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let challenge_data = Self {
+            issued_at: issued_at.parse().map_err(|_| invalid())?,
+            salt: salt.to_string(),
+        };
+        let tag = hex::decode(tag).map_err(|_| invalid())?;
+        challenge_data
+            .mac(secret)
+            .verify_slice(&tag)
+            .map_err(|_| invalid())?;
+        let issued_at =
+            DateTime::from_timestamp(challenge_data.issued_at, 0).ok_or_else(invalid)?;
+        if issued_at + max_age < now {
+            return Err(Error::BadRequest(
+                "The proof of work challenge has expired.".to_string(),
+            ));
+        }
+        let hash = Sha256::digest(format!("{}:{}:{}", challenge, email, nonce));
+        if leading_zero_bits(&hash) < u32::from(difficulty) {
+            return Err(invalid());
+        }
+        Ok(challenge_data)
+    }
+
+    /// Tells challenges apart, to record which ones were used.
+    pub fn salt(&self) -> &str {
+        &self.salt
+    }
+
+    fn mac(&self, secret: &Secret<String>) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(b"proof_of_work:");
+        mac.update(self.issued_at.to_string().as_bytes());
+        mac.update(b":");
+        mac.update(self.salt.as_bytes());
+        mac
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+
+    use crate::domain::proof_of_work::{leading_zero_bits, ProofOfWorkChallenge};
+
+    const DIFFICULTY: u8 = 8;
+
+    fn secret() -> Secret<String> {
+        Secret::new(Uuid::new_v4().to_string())
+    }
+
+    const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+    fn solve(challenge: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| {
+                let hash = Sha256::digest(format!("{}:{}:{}", challenge, EMAIL, nonce));
+                leading_zero_bits(&hash) >= u32::from(difficulty)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn a_solved_challenge_is_accepted() {
+        let secret = secret();
+        let challenge = ProofOfWorkChallenge::issue(Utc::now()).sign(&secret);
+        let nonce = solve(&challenge, DIFFICULTY);
+        assert_ok!(ProofOfWorkChallenge::verify(
+            &secret,
+            &challenge,
+            EMAIL,
+            &nonce,
+            DIFFICULTY,
+            Duration::minutes(10),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn a_solution_only_holds_for_the_email_it_was_solved_for() {
+        let secret = secret();
+        let challenge = ProofOfWorkChallenge::issue(Utc::now()).sign(&secret);
+        let nonce = (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| {
+                let solves = |email: &str| {
+                    let hash = Sha256::digest(format!("{}:{}:{}", challenge, email, nonce));
+                    leading_zero_bits(&hash) >= u32::from(DIFFICULTY)
+                };
+                solves(EMAIL) && !solves("octavia_butler@gmail.com")
+            })
+            .unwrap();
+        assert_err!(ProofOfWorkChallenge::verify(
+            &secret,
+            &challenge,
+            "octavia_butler@gmail.com",
+            &nonce,
+            DIFFICULTY,
+            Duration::minutes(10),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn a_nonce_without_enough_work_is_rejected() {
+        let secret = secret();
+        let challenge = ProofOfWorkChallenge::issue(Utc::now()).sign(&secret);
+        let nonce = (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| {
+                let hash = Sha256::digest(format!("{}:{}:{}", challenge, EMAIL, nonce));
+                leading_zero_bits(&hash) == 0
+            })
+            .unwrap();
+        assert_err!(ProofOfWorkChallenge::verify(
+            &secret,
+            &challenge,
+            EMAIL,
+            &nonce,
+            DIFFICULTY,
+            Duration::minutes(10),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn a_challenge_signed_with_another_secret_is_rejected() {
+        let challenge = ProofOfWorkChallenge::issue(Utc::now()).sign(&secret());
+        let nonce = solve(&challenge, DIFFICULTY);
+        assert_err!(ProofOfWorkChallenge::verify(
+            &secret(),
+            &challenge,
+            EMAIL,
+            &nonce,
+            DIFFICULTY,
+            Duration::minutes(10),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn an_expired_challenge_is_rejected() {
+        let secret = secret();
+        let challenge =
+            ProofOfWorkChallenge::issue(Utc::now() - Duration::minutes(11)).sign(&secret);
+        let nonce = solve(&challenge, DIFFICULTY);
+        assert_err!(ProofOfWorkChallenge::verify(
+            &secret,
+            &challenge,
+            EMAIL,
+            &nonce,
+            DIFFICULTY,
+            Duration::minutes(10),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn malformed_challenges_are_rejected() {
+        let secret = secret();
+        for challenge in ["", "1.2", "x.abc.def", "1.2.3.4"] {
+            assert_err!(ProofOfWorkChallenge::verify(
+                &secret,
+                challenge,
+                EMAIL,
+                "0",
+                0,
+                Duration::minutes(10),
+                Utc::now(),
+            ));
+        }
+    }
+}
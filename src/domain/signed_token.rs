@@ -0,0 +1,226 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{errors::Error, Result};
+
+/// The action a signed token grants, so a link issued for one action
+/// cannot be replayed against another.
+#[derive(Debug, Clone, Copy)]
+pub enum TokenPurpose {
+    Unsubscribe,
+    Preferences,
+    Export,
+    Erase,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Unsubscribe => "unsubscribe",
+            TokenPurpose::Preferences => "preferences",
+            TokenPurpose::Export => "export",
+            TokenPurpose::Erase => "erase",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SignedToken(String);
+
+impl SignedToken {
+    pub fn sign(secret: &Secret<String>, purpose: TokenPurpose, subscriber_id: Uuid) -> Self {
+        let tag = mac(secret, purpose, subscriber_id, None)
+            .finalize()
+            .into_bytes();
+        Self(hex::encode(tag))
+    }
+
+    /// Signs a token that also covers when it was issued, so it can be given
+    /// an expiry with [`SignedToken::verify_issued_at`].
+    pub fn sign_issued_at(
+        secret: &Secret<String>,
+        purpose: TokenPurpose,
+        subscriber_id: Uuid,
+        issued_at: i64,
+    ) -> Self {
+        let tag = mac(secret, purpose, subscriber_id, Some(issued_at))
+            .finalize()
+            .into_bytes();
+        Self(hex::encode(tag))
+    }
+
+    pub fn parse(s: String) -> Result<SignedToken> {
+        if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(s))
+        } else {
+            Err(Error::Unauthorized(format!(
+                "{} is not a valid signed token.",
+                s
+            )))
+        }
+    }
+
+    pub fn verify(
+        &self,
+        secret: &Secret<String>,
+        purpose: TokenPurpose,
+        subscriber_id: Uuid,
+    ) -> Result<()> {
+        self.verify_mac(mac(secret, purpose, subscriber_id, None))
+    }
+
+    /// Checks a token from [`SignedToken::sign_issued_at`], which is only
+    /// good for `max_age` after `issued_at`.
+    pub fn verify_issued_at(
+        &self,
+        secret: &Secret<String>,
+        purpose: TokenPurpose,
+        subscriber_id: Uuid,
+        issued_at: i64,
+        max_age: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.verify_mac(mac(secret, purpose, subscriber_id, Some(issued_at)))?;
+        let issued_at = DateTime::from_timestamp(issued_at, 0)
+            .ok_or_else(|| Error::Unauthorized("Invalid token timestamp.".to_string()))?;
+        if issued_at + max_age < now {
+            return Err(Error::Unauthorized(
+                "The signed token has expired.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn verify_mac(&self, mac: Hmac<Sha256>) -> Result<()> {
+        let tag = hex::decode(&self.0)
+            .map_err(|_| Error::Unauthorized("Failed to decode signed token.".to_string()))?;
+        mac.verify_slice(&tag)
+            .map_err(|_| Error::Unauthorized("Failed to verify signed token.".to_string()))
+    }
+}
+
+impl AsRef<str> for SignedToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn mac(
+    secret: &Secret<String>,
+    purpose: TokenPurpose,
+    subscriber_id: Uuid,
+    issued_at: Option<i64>,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(purpose.as_str().as_bytes());
+    mac.update(b":");
+    mac.update(subscriber_id.as_bytes());
+    if let Some(issued_at) = issued_at {
+        mac.update(b":");
+        mac.update(issued_at.to_string().as_bytes());
+    }
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use crate::domain::signed_token::{SignedToken, TokenPurpose};
+
+    fn secret() -> Secret<String> {
+        Secret::new(Uuid::new_v4().to_string())
+    }
+
+    #[test]
+    fn a_freshly_signed_token_is_verified() {
+        let secret = secret();
+        let subscriber_id = Uuid::new_v4();
+        let token = SignedToken::sign(&secret, TokenPurpose::Unsubscribe, subscriber_id);
+        let token = SignedToken::parse(token.as_ref().to_string()).unwrap();
+        assert_ok!(token.verify(&secret, TokenPurpose::Unsubscribe, subscriber_id));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected() {
+        let secret = secret();
+        let token = SignedToken::sign(&secret, TokenPurpose::Unsubscribe, Uuid::new_v4());
+        assert_err!(token.verify(&secret, TokenPurpose::Unsubscribe, Uuid::new_v4()));
+    }
+
+    #[test]
+    fn a_token_for_another_purpose_is_rejected() {
+        let secret = secret();
+        let subscriber_id = Uuid::new_v4();
+        let token = SignedToken::sign(&secret, TokenPurpose::Unsubscribe, subscriber_id);
+        assert_err!(token.verify(&secret, TokenPurpose::Preferences, subscriber_id));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let token = SignedToken::sign(&secret(), TokenPurpose::Unsubscribe, subscriber_id);
+        assert_err!(token.verify(&secret(), TokenPurpose::Unsubscribe, subscriber_id));
+    }
+
+    #[test]
+    fn a_token_with_an_issue_time_expires() {
+        let secret = secret();
+        let subscriber_id = Uuid::new_v4();
+        let issued_at = Utc::now() - Duration::hours(2);
+        let token = SignedToken::sign_issued_at(
+            &secret,
+            TokenPurpose::Export,
+            subscriber_id,
+            issued_at.timestamp(),
+        );
+        let verify = |max_age| {
+            token.verify_issued_at(
+                &secret,
+                TokenPurpose::Export,
+                subscriber_id,
+                issued_at.timestamp(),
+                max_age,
+                Utc::now(),
+            )
+        };
+        assert_ok!(verify(Duration::hours(3)));
+        assert_err!(verify(Duration::hours(1)));
+    }
+
+    #[test]
+    fn the_issue_time_of_a_token_cannot_be_changed() {
+        let secret = secret();
+        let subscriber_id = Uuid::new_v4();
+        let issued_at = Utc::now() - Duration::hours(2);
+        let token = SignedToken::sign_issued_at(
+            &secret,
+            TokenPurpose::Erase,
+            subscriber_id,
+            issued_at.timestamp(),
+        );
+        assert_err!(token.verify_issued_at(
+            &secret,
+            TokenPurpose::Erase,
+            subscriber_id,
+            Utc::now().timestamp(),
+            Duration::hours(1),
+            Utc::now(),
+        ));
+        assert_err!(token.verify(&secret, TokenPurpose::Erase, subscriber_id));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        assert_err!(SignedToken::parse("".to_string()));
+        assert_err!(SignedToken::parse("not-a-hex-token".to_string()));
+        assert_err!(SignedToken::parse("a".repeat(63)));
+    }
+}
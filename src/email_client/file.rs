@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Mutex};
+
+use crate::Result;
+
+use super::{Email, EmailTransport};
+
+/// Appends emails to a local mbox file instead of sending them, so the stack
+/// can run without an email provider.
+pub struct FileTransport {
+    path: PathBuf,
+    // Serialises appends so concurrent messages don't interleave.
+    lock: Mutex<()>,
+}
+
+impl FileTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<Option<String>> {
+        let message = email.to_message()?;
+        let message_id = message
+            .headers()
+            .get_raw("Message-ID")
+            .map(ToString::to_string);
+        let entry = mbox_entry(
+            email.from.as_ref(),
+            &String::from_utf8_lossy(&message.formatted()),
+        );
+
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(entry.as_bytes()).await?;
+        file.flush().await?;
+        Ok(message_id)
+    }
+}
+
+/// Formats a message as an mboxrd entry: a `From ` separator line, the
+/// message with `From ` lines quoted, and a trailing blank line.
+fn mbox_entry(sender: &str, message: &str) -> String {
+    let mut entry = format!(
+        "From {} {}\n",
+        sender,
+        chrono::Utc::now().format("%a %b %e %T %Y")
+    );
+    for line in message.lines() {
+        if line.trim_start_matches('>').starts_with("From ") {
+            entry.push('>');
+        }
+        entry.push_str(line);
+        entry.push('\n');
+    }
+    entry.push('\n');
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{file::mbox_entry, EmailClient, FileTransport},
+    };
+
+    #[tokio::test]
+    async fn send_email_appends_messages_to_the_mbox_file() {
+        let path = std::env::temp_dir().join(format!("{}.mbox", Uuid::new_v4()));
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            FileTransport::new(&path),
+        );
+
+        for subject in ["First", "Second"] {
+            let outcome = email_client
+                .send_email(
+                    SubscriberEmail::parse("recipient@example.com".into()).unwrap(),
+                    subject,
+                    "<p>Hello</p>",
+                    "Hello",
+                    None,
+                )
+                .await;
+            assert_ok!(outcome);
+        }
+
+        let mbox = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mbox.matches("\nFrom sender@example.com ").count(), 1);
+        assert!(mbox.starts_with("From sender@example.com "));
+        assert!(mbox.contains("Subject: First"));
+        assert!(mbox.contains("Subject: Second"));
+    }
+
+    #[tokio::test]
+    async fn text_only_emails_are_not_multipart() {
+        let path = std::env::temp_dir().join(format!("{}.mbox", Uuid::new_v4()));
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            FileTransport::new(&path),
+        );
+
+        let outcome = email_client
+            .send_email(
+                SubscriberEmail::parse("recipient@example.com".into()).unwrap(),
+                "Plain",
+                "",
+                "Hello",
+                None,
+            )
+            .await;
+        assert_ok!(outcome);
+
+        let mbox = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(mbox.contains("Content-Type: text/plain"));
+        assert!(!mbox.contains("multipart"));
+        assert!(!mbox.contains("text/html"));
+    }
+
+    #[test]
+    fn from_lines_in_the_body_are_quoted() {
+        let entry = mbox_entry(
+            "sender@example.com",
+            "Subject: Hi\r\n\r\nFrom here\r\n>From there",
+        );
+        assert!(entry.contains("\n>From here\n"));
+        assert!(entry.contains("\n>>From there\n"));
+    }
+}
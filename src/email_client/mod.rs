@@ -0,0 +1,157 @@
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+use lettre::message::{
+    header::{ContentType, HeaderName, HeaderValue},
+    Mailbox, MultiPart,
+};
+
+use crate::{domain::SubscriberEmail, Result};
+
+/// A single outgoing email, independent of how it is delivered. An empty
+/// `html_body` makes it a plain text only email.
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl Email<'_> {
+    /// Builds the MIME message sent by the SMTP and file transports.
+    pub fn to_message(&self) -> Result<lettre::Message> {
+        let mut builder = lettre::Message::builder()
+            .from(self.from.as_ref().parse::<Mailbox>()?)
+            .to(self.to.as_ref().parse::<Mailbox>()?)
+            .subject(self.subject)
+            .message_id(None);
+        for (name, value) in &self.headers {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str(name),
+                value.clone(),
+            ));
+        }
+        if self.html_body.is_empty() {
+            return Ok(builder
+                .header(ContentType::TEXT_PLAIN)
+                .body(self.text_body.to_string())?);
+        }
+        Ok(builder.multipart(MultiPart::alternative_plain_html(
+            self.text_body.to_string(),
+            self.html_body.to_string(),
+        ))?)
+    }
+}
+
+/// One message of a batch sent with [`EmailClient::send_email_batch`].
+pub struct OutgoingEmail<'a> {
+    pub recipient: SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_url: Option<String>,
+}
+
+/// Delivers an email, returning the provider's message id when it has one.
+#[axum::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<Option<String>>;
+
+    /// Delivers several emails at once. An error means the whole batch failed,
+    /// otherwise there is one result per email, in the same order.
+    /// Transports without a batch API send the emails one by one.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<Result<Option<String>>>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(email).await);
+        }
+        Ok(results)
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_url: Option<&str>,
+    ) -> Result<Option<String>> {
+        let email = self.email(
+            &recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_url,
+        );
+        self.transport.send(&email).await
+    }
+
+    pub async fn send_email_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<Option<String>>>> {
+        let emails = emails
+            .iter()
+            .map(|e| {
+                self.email(
+                    &e.recipient,
+                    e.subject,
+                    e.html_content,
+                    e.text_content,
+                    e.unsubscribe_url.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.transport.send_batch(&emails).await
+    }
+
+    fn email<'a>(
+        &'a self,
+        recipient: &'a SubscriberEmail,
+        subject: &'a str,
+        html_content: &'a str,
+        text_content: &'a str,
+        unsubscribe_url: Option<&str>,
+    ) -> Email<'a> {
+        // RFC 8058 one-click unsubscribe: mail clients POST to the URL directly.
+        let headers = match unsubscribe_url {
+            Some(unsubscribe_url) => vec![
+                ("List-Unsubscribe", format!("<{}>", unsubscribe_url)),
+                (
+                    "List-Unsubscribe-Post",
+                    "List-Unsubscribe=One-Click".to_string(),
+                ),
+            ],
+            None => vec![],
+        };
+        Email {
+            from: &self.sender,
+            to: recipient,
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        }
+    }
+}
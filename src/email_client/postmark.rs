@@ -0,0 +1,451 @@
+use reqwest::{Client, RequestBuilder};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, Result};
+
+use super::{Email, EmailTransport};
+
+/// Postmark accepts at most this many messages per `/email/batch` request.
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Sends emails through Postmark's `/email` and `/email/batch` JSON APIs.
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.http_client
+            .post(format!("{}{}", self.base_url, path))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+    }
+
+    async fn send_chunk(&self, emails: &[Email<'_>]) -> Result<Vec<Result<Option<String>>>> {
+        let request_body = emails
+            .iter()
+            .map(SendEmailRequest::from)
+            .collect::<Vec<_>>();
+        let responses: Vec<SendEmailResponse> = self
+            .post("/email/batch")
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if responses.len() != emails.len() {
+            return Err(Error::Message(format!(
+                "Postmark returned {} results for a batch of {} emails.",
+                responses.len(),
+                emails.len()
+            )));
+        }
+        Ok(responses
+            .into_iter()
+            .map(|response| match response.error_code {
+                0 => Ok(response.message_id),
+                error_code => Err(Error::Message(format!(
+                    "Postmark rejected the email ({}): {}",
+                    error_code, response.message
+                ))),
+            })
+            .collect())
+    }
+}
+
+#[axum::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<Option<String>> {
+        let response = self
+            .post("/email")
+            .json(&SendEmailRequest::from(email))
+            .send()
+            .await?
+            .error_for_status()?;
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_id);
+        Ok(message_id)
+    }
+
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<Result<Option<String>>>> {
+        if let [email] = emails {
+            return Ok(vec![self.send(email).await]);
+        }
+        let mut results = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                // Keep the results of the chunks already sent.
+                Err(e) => results.extend(chunk.iter().map(|_| Err(Error::Message(e.to_string())))),
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+impl<'a> From<&'a Email<'a>> for SendEmailRequest<'a> {
+    fn from(email: &'a Email<'a>) -> Self {
+        Self {
+            from: email.from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email
+                .headers
+                .iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    #[serde(default)]
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::SecretString;
+
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, OutgoingEmail, PostmarkTransport},
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(
+                base_url,
+                SecretString::new(Faker.fake()),
+                std::time::Duration::from_millis(200),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(email(), &subject(), &content(), &content(), None)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_sets_list_unsubscribe_headers_if_requested() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                Some("https://example.com/unsubscribe"),
+            )
+            .await;
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_omits_the_html_body_of_text_only_emails() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(email(), &subject(), "", "Hello", None)
+            .await;
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("HtmlBody").is_none());
+        assert_eq!(body["TextBody"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn send_email_success_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content(), None)
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content(), None)
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content(), None)
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    fn outgoing_email(subject: &str) -> OutgoingEmail<'_> {
+        OutgoingEmail {
+            recipient: email(),
+            subject,
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            unsubscribe_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fires_a_single_request_to_the_batch_endpoint() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "first-id"},
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "second-id"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_email_batch(&[outgoing_email("First"), outgoing_email("Second")])
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body[0]["Subject"], "First");
+        assert_eq!(body[1]["Subject"], "Second");
+        let message_ids = results.into_iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            message_ids,
+            vec![Some("first-id".to_string()), Some("second-id".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_errors_for_each_message() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "first-id"},
+                {"ErrorCode": 406, "Message": "Inactive recipient"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_email_batch(&[outgoing_email("First"), outgoing_email("Second")])
+            .await
+            .unwrap();
+
+        assert_ok!(&results[0]);
+        assert_err!(&results[1]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_every_message_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_email_batch(&[outgoing_email("First"), outgoing_email("Second")])
+            .await
+            .unwrap();
+
+        assert!(results.iter().all(|r| r.is_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_of_one_uses_the_single_email_endpoint() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_email_batch(&[outgoing_email("Only")])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_ok!(&results[0]);
+    }
+}
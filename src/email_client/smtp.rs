@@ -0,0 +1,162 @@
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use crate::{
+    configuration::{SmtpSettings, SmtpTls},
+    Result,
+};
+
+use super::{Email, EmailTransport};
+
+/// Sends emails to an SMTP relay, optionally over TLS and with credentials.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(settings: &SmtpSettings, timeout: std::time::Duration) -> Result<Self> {
+        let builder = match settings.tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        };
+        let mut builder = builder.port(settings.port).timeout(Some(timeout));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[axum::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<Option<String>> {
+        let message = email.to_message()?;
+        let message_id = message
+            .headers()
+            .get_raw("Message-ID")
+            .map(ToString::to_string);
+        self.mailer.send(message).await?;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use crate::{
+        configuration::{SmtpSettings, SmtpTls},
+        domain::SubscriberEmail,
+        email_client::{EmailClient, SmtpTransport},
+    };
+
+    fn email_client(port: u16) -> EmailClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+        };
+        EmailClient::new(
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            SmtpTransport::new(&settings, std::time::Duration::from_secs(2)).unwrap(),
+        )
+    }
+
+    /// A minimal SMTP server accepting a single message, answering its DATA
+    /// section with `reply_to_data` and returning what it received.
+    async fn smtp_server(listener: TcpListener, reply_to_data: &'static str) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if in_data {
+                if line == "." {
+                    writer.write_all(reply_to_data.as_bytes()).await.unwrap();
+                    break;
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let reply: &[u8] = match line.split_whitespace().next().unwrap_or_default() {
+                "EHLO" => b"250 localhost\r\n",
+                "DATA" => {
+                    in_data = true;
+                    b"354 Start mail input\r\n"
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(smtp_server(listener, "250 OK\r\n"));
+
+        let outcome = email_client(port)
+            .send_email(
+                SubscriberEmail::parse("recipient@example.com".into()).unwrap(),
+                "Hello",
+                "<p>Hello</p>",
+                "Hello",
+                Some("https://example.com/unsubscribe"),
+            )
+            .await;
+
+        let message_id = assert_ok!(outcome);
+        assert!(message_id.is_some());
+        let data = tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(data.contains("Subject: Hello"));
+        assert!(data.contains("To: recipient@example.com"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_smtp_server_rejects_the_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(smtp_server(listener, "554 Transaction failed\r\n"));
+
+        let outcome = email_client(port)
+            .send_email(
+                SubscriberEmail::parse("recipient@example.com".into()).unwrap(),
+                "Hello",
+                "<p>Hello</p>",
+                "Hello",
+                None,
+            )
+            .await;
+
+        assert_err!(outcome);
+    }
+}
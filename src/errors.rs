@@ -28,6 +28,12 @@ pub enum Error {
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
     #[error(transparent)]
+    EmailAddress(#[from] lettre::address::AddressError),
+    #[error(transparent)]
+    EmailMessage(#[from] lettre::error::Error),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
     Base64Decode(#[from] base64::DecodeError),
     #[error(transparent)]
     FromUtf8(#[from] string::FromUtf8Error),
@@ -45,6 +51,14 @@ pub enum Error {
     InvalidStatusCode(#[from] axum::http::status::InvalidStatusCode),
     #[error(transparent)]
     AxumError(#[from] axum::Error),
+    #[error(transparent)]
+    Multipart(#[from] axum::extract::multipart::MultipartError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+    #[error(transparent)]
+    RedisPool(#[from] redis_pool::errors::RedisPoolError),
 
     // API
     #[error("not found")]
@@ -53,6 +67,10 @@ pub enum Error {
     BadRequest(String),
     #[error("{0}")]
     Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    TooManyRequests(String),
     #[error("internal server error")]
     InternalServerError,
     #[error("")]
@@ -172,6 +190,20 @@ impl IntoResponse for Error {
                     ),
                 )
             }
+            Self::Forbidden(err) => {
+                tracing::warn!(err);
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorDetail::new("forbidden", "Your role does not allow this action"),
+                )
+            }
+            Self::TooManyRequests(err) => {
+                tracing::warn!(err);
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    ErrorDetail::new("too_many_requests", "Too many requests, try again later"),
+                )
+            }
             Self::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 ErrorDetail::new("internal_server_error", "Internal Server Error"),
@@ -118,7 +118,7 @@ pub async fn save_response(
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    StartProcessing(Box<Transaction<'static, Postgres>>),
     ReturnSavedResponse(Response<Body>),
 }
 
@@ -143,7 +143,7 @@ pub async fn try_processing(
     .bind(idempotency_key.as_ref());
     let n_inserted_rows = transaction.execute(query).await?.rows_affected();
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
+        Ok(NextAction::StartProcessing(Box::new(transaction)))
     } else {
         let saved_response = get_saved_response(pool, idempotency_key, user_id)
             .await?
@@ -1,21 +1,58 @@
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    num::NonZeroU16,
+    time::{Duration, Instant},
+};
 
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
 use sqlx::{postgres::PgPoolOptions, prelude::FromRow, Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::{configuration::Settings, domain::SubscriberEmail, email_client::EmailClient, Result};
+use crate::{
+    configuration::{IssueDeliverySettings, Settings},
+    controller::unsubscribe_link,
+    domain::{DeliveryFormat, IssueTemplate, Recipient, SubscriberEmail},
+    email_client::{EmailClient, OutgoingEmail},
+    errors::Error,
+    Result,
+};
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<()> {
     let connection_pool = PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
         .connect_lazy_with(configuration.database.with_db());
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+        configuration.issue_delivery,
+    )
+    .await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<()> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    settings: IssueDeliverySettings,
+) -> Result<()> {
+    let mut next_promotion = Instant::now();
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        if Instant::now() >= next_promotion {
+            if let Err(e) = promote_scheduled_issues(&pool).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to promote scheduled newsletter issues",
+                );
+            }
+            next_promotion = Instant::now() + Duration::from_secs(10);
+        }
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_secret, &settings).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -32,91 +69,379 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
+/// What happened to a single recipient of an issue, recorded once the
+/// queue row for them is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    Sent,
+    Failed,
+    SkippedInvalidEmail,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::SkippedInvalidEmail => "skipped_invalid_email",
+        }
+    }
+}
+
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    settings: &IssueDeliverySettings,
 ) -> Result<ExecutionOutcome> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    let (mut transaction, tasks) = dequeue_tasks(pool, settings.batch_size).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
 
-    let (transaction, issue_id, email) = task.unwrap();
-    tracing::info!("Delivering issue {} to {}", issue_id, email);
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    email,
+    // Issues that fail to load, or whose templates fail to parse, fail their
+    // tasks below rather than the batch.
+    let mut issues = HashMap::new();
+    for task in &tasks {
+        if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+            entry.insert(match get_issue(pool, task.newsletter_issue_id).await {
+                Ok(issue) => Ok(IssueTemplate::parse(
                     &issue.title,
                     &issue.html_content,
                     &issue.text_content,
                 )
-                .await
-            {
+                .map_err(|e| e.to_string())),
+                Err(e) => Err(e.to_string()),
+            });
+        }
+    }
+
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    let mut rendered = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        let recipient = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(recipient) => recipient,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Skipping a confirmed subscriber. \
+                        Their stored contact details are invalid",
+                );
+                record_outcome(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
+                    DeliveryOutcome::SkippedInvalidEmail,
+                    None,
+                )
+                .await?;
+                delete_task(&mut transaction, task).await?;
+                continue;
+            }
+        };
+        let unsubscribe_url = task
+            .subscriber_id
+            .map(|subscriber_id| unsubscribe_link(base_url, hmac_secret, subscriber_id));
+        let template = match &issues[&task.newsletter_issue_id] {
+            Ok(template) => template,
+            Err(e) => {
+                let e = Error::Message(e.clone());
+                retry_or_fail_task(&mut transaction, settings, task, &e).await?;
+                continue;
+            }
+        };
+        let issue = match template {
+            Ok(template) => template.render(&Recipient::new(
+                task.subscriber_name.clone(),
+                unsubscribe_url.clone(),
+                task.subscribed_at,
+            )),
+            Err(e) => Err(Error::Message(e.clone())),
+        };
+        match issue {
+            Ok(issue) => {
+                deliverable.push((task, recipient, unsubscribe_url));
+                rendered.push(issue);
+            }
+            Err(e) => {
+                // Rendering again won't help, so there is no point retrying.
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping.",
+                    "Failed to render issue for a confirmed subscriber. Giving up.",
                 );
+                fail_task(&mut transaction, task, &e.to_string()).await?;
             }
         }
+    }
+    let (deliverable, emails): (Vec<_>, Vec<_>) = deliverable
+        .into_iter()
+        .zip(&rendered)
+        .map(|((task, recipient, unsubscribe_url), issue)| {
+            let html_content = match task.delivery_format.as_deref() {
+                Some(format) if format == DeliveryFormat::Text.as_str() => "",
+                _ => &issue.html_content,
+            };
+            let email = OutgoingEmail {
+                recipient,
+                subject: &issue.title,
+                html_content,
+                text_content: &issue.text_content,
+                unsubscribe_url,
+            };
+            (task, email)
+        })
+        .unzip();
+
+    tracing::info!("Delivering a batch of {} emails", emails.len());
+    let results = match email_client.send_email_batch(&emails).await {
+        Ok(results) => results,
         Err(e) => {
-            tracing::error!(
-                error.cause_chain = ?e,
-                error.message = %e,
-                "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",
-            );
+            let message = e.to_string();
+            deliverable
+                .iter()
+                .map(|_| Err(Error::Message(message.clone())))
+                .collect()
+        }
+    };
+    for (task, result) in deliverable.into_iter().zip(results) {
+        match result {
+            Ok(message_id) => {
+                record_outcome(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
+                    DeliveryOutcome::Sent,
+                    message_id.as_deref(),
+                )
+                .await?;
+                delete_task(&mut transaction, task).await?;
+            }
+            Err(e) => retry_or_fail_task(&mut transaction, settings, task, &e).await?,
         }
     }
-    delete_task(transaction, issue_id, &email).await?;
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
-type PgTransaction = Transaction<'static, Postgres>;
-async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String)>> {
+/// Retries a task that failed to deliver after a backoff, or moves it to the
+/// dead-letter table once its retries are exhausted.
+async fn retry_or_fail_task(
+    transaction: &mut PgTransaction,
+    settings: &IssueDeliverySettings,
+    task: &Task,
+    e: &Error,
+) -> Result<()> {
+    if task.n_retries >= settings.max_retries {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to deliver issue to a confirmed subscriber. \
+                Retries exhausted, giving up.",
+        );
+        fail_task(transaction, task, &e.to_string()).await
+    } else {
+        let backoff = settings.backoff(task.n_retries);
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to deliver issue to a confirmed subscriber. \
+                Retrying in {:?}.",
+            backoff,
+        );
+        retry_task(transaction, task, backoff).await
+    }
+}
+
+/// Publishes the scheduled issues that are due and enqueues their delivery
+/// tasks, returning how many issues were promoted.
+pub async fn promote_scheduled_issues(pool: &PgPool) -> Result<usize> {
     let mut transaction = pool.begin().await?;
-    #[derive(FromRow)]
-    struct Row {
-        newsletter_issue_id: Uuid,
-        subscriber_email: String,
+    let issue_ids: Vec<(Uuid,)> = sqlx::query_as(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now()
+        WHERE status = 'scheduled' AND scheduled_for <= now()
+        RETURNING newsletter_issue_id
+        "#,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for (issue_id,) in &issue_ids {
+        tracing::info!("Publishing scheduled issue {}", issue_id);
+        enqueue_delivery_tasks(&mut transaction, *issue_id).await?;
     }
-    let r: Option<Row> = sqlx::query_as(
+    transaction.commit().await?;
+    Ok(issue_ids.len())
+}
+
+/// Queues the issue for the confirmed members of the lists it targets, once
+/// per address even when they are on several of them.
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<()> {
+    let query = sqlx::query(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT DISTINCT $1, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issue_lists l ON l.list_id = m.list_id
+        WHERE l.newsletter_issue_id = $1 AND s.status = 'confirmed'
+        "#,
+    )
+    .bind(newsletter_issue_id);
+
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[derive(FromRow)]
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    subscriber_id: Option<Uuid>,
+    subscriber_name: Option<String>,
+    subscribed_at: Option<DateTime<Utc>>,
+    delivery_format: Option<String>,
+}
+
+/// Locks up to `batch_size` due tasks; they stay locked until the returned
+/// transaction is committed.
+async fn dequeue_tasks(
+    pool: &PgPool,
+    batch_size: NonZeroU16,
+) -> Result<(PgTransaction, Vec<Task>)> {
+    let mut transaction = pool.begin().await?;
+    let tasks = sqlx::query_as(
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            s.id AS subscriber_id,
+            s.name AS subscriber_name,
+            s.subscribed_at,
+            s.delivery_format
+        FROM issue_delivery_queue q
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
         "#,
     )
-    .fetch_optional(&mut *transaction)
+    .bind(i64::from(batch_size.get()))
+    .fetch_all(&mut *transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok((transaction, tasks))
 }
 
-async fn delete_task(mut transaction: PgTransaction, issue_id: Uuid, email: &str) -> Result<()> {
+async fn delete_task(transaction: &mut PgTransaction, task: &Task) -> Result<()> {
     let query = sqlx::query(
         r#"
         DELETE FROM issue_delivery_queue
         WHERE newsletter_issue_id = $1 AND subscriber_email = $2
         "#,
     )
+    .bind(task.newsletter_issue_id)
+    .bind(&task.subscriber_email);
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+async fn retry_task(transaction: &mut PgTransaction, task: &Task, backoff: Duration) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+    )
+    .bind(task.newsletter_issue_id)
+    .bind(&task.subscriber_email)
+    .bind(Utc::now() + backoff);
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Moves a task that exhausted its retries to the dead-letter table.
+async fn fail_task(transaction: &mut PgTransaction, task: &Task, error: &str) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+    )
+    .bind(task.newsletter_issue_id)
+    .bind(&task.subscriber_email)
+    .bind(task.n_retries)
+    .bind(error);
+    transaction.execute(query).await?;
+    record_outcome(
+        transaction,
+        task.newsletter_issue_id,
+        &task.subscriber_email,
+        DeliveryOutcome::Failed,
+        None,
+    )
+    .await?;
+    delete_task(transaction, task).await
+}
+
+async fn record_outcome(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    outcome: DeliveryOutcome,
+    provider_message_id: Option<&str>,
+) -> Result<()> {
+    let query = sqlx::query(
+        r#"
+        INSERT INTO issue_delivery_outcomes (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            provider_message_id,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            provider_message_id = EXCLUDED.provider_message_id,
+            recorded_at = EXCLUDED.recorded_at
+        "#,
+    )
     .bind(issue_id)
-    .bind(email);
+    .bind(email)
+    .bind(outcome.as_str())
+    .bind(provider_message_id);
     transaction.execute(query).await?;
-    transaction.commit().await?;
     Ok(())
 }
 
@@ -12,6 +12,7 @@ pub mod issue_delivery_worker;
 pub mod middleware;
 pub mod startup;
 pub mod telemetry;
+pub mod token_cleanup_worker;
 pub mod view_engine;
 
 /// Application results options list
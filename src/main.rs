@@ -6,6 +6,7 @@ use zero2prod::{
     issue_delivery_worker::run_worker_until_stopped,
     startup::{run_until_stopped, AppState},
     telemetry::init,
+    token_cleanup_worker::run_token_cleanup_until_stopped,
     Result,
 };
 
@@ -16,9 +17,11 @@ async fn main() -> Result<()> {
     let app_state = AppState::build(&configuration).await;
     let application_task = tokio::spawn(run_until_stopped(app_state, configuration.clone()));
     let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let cleanup_task = tokio::spawn(run_token_cleanup_until_stopped(configuration.clone()));
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task =>  report_exit("Background worker", o),
+        o = cleanup_task => report_exit("Token cleanup worker", o),
     };
     Ok(())
 }
@@ -1,14 +1,20 @@
-use axum::{extract::Request, middleware::Next, response::Response};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
 use axum_session::Session;
 use axum_session_redispool::SessionRedisPool;
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{controller::render, Result};
+use crate::{controller::render, domain::Role, errors::Error, startup::AppState, Result};
 
 #[derive(Clone)]
 pub struct UserId(pub Uuid);
 
 pub async fn auth_middleware(
+    State(state): State<AppState>,
     session: Session<SessionRedisPool>,
     mut request: Request,
     next: Next,
@@ -19,6 +25,42 @@ pub async fn auth_middleware(
         Some(user_id) => user_id,
         None => return render().redirect("/login"),
     };
+    // Looked up on every request, so deactivating a user or resetting their
+    // password locks them out straight away.
+    let session_epoch = session.get::<i32>("session_epoch").unwrap_or_default();
+    let role = match get_active_role(&state.db_pool, user_id, session_epoch).await? {
+        Some(role) => role,
+        None => {
+            session.destroy();
+            return render().redirect("/login");
+        }
+    };
     request.extensions_mut().insert(user_id);
+    request.extensions_mut().insert(role);
     Ok(next.run(request).await)
 }
+
+/// Only lets through users whose role is at least `required`; runs after
+/// [`auth_middleware`].
+pub async fn require_role(required: Role, request: Request, next: Next) -> Result<Response> {
+    match request.extensions().get::<Role>() {
+        Some(role) if *role >= required => Ok(next.run(request).await),
+        _ => Err(Error::Forbidden(format!(
+            "The {} role is required.",
+            required.as_str()
+        ))),
+    }
+}
+
+async fn get_active_role(pool: &PgPool, user_id: Uuid, session_epoch: i32) -> Result<Option<Role>> {
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"
+        SELECT role FROM users WHERE user_id = $1 AND is_active AND session_epoch = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(session_epoch)
+    .fetch_optional(pool)
+    .await?;
+    row.map(|(role,)| Role::parse(&role)).transpose()
+}
@@ -0,0 +1,44 @@
+use std::{convert::Infallible, net::IpAddr, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::startup::AppState;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The address of the client that sent the request, if it is known.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.behind_proxy {
+            // Clients can send their own X-Forwarded-For, so only the entry
+            // appended by our proxy can be trusted.
+            let forwarded = parts
+                .headers
+                .get_all(X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|header| header.to_str().ok())
+                .flat_map(|header| header.split(','))
+                .last()
+                .and_then(|ip| ip.trim().parse().ok());
+            return Ok(Self(forwarded));
+        }
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self(peer))
+    }
+}
@@ -1,6 +1,8 @@
 mod auth;
+mod client_ip;
 mod request_id;
 
-pub use auth::auth_middleware;
 pub use auth::UserId;
+pub use auth::{auth_middleware, require_role};
+pub use client_ip::ClientIp;
 pub use request_id::{request_id_middleware, Zero2prodRequestId};
@@ -1,28 +1,47 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
+    extract::DefaultBodyLimit,
     http,
+    middleware::{from_fn, from_fn_with_state},
     routing::{get, post},
     Router,
 };
 use axum_messages::MessagesManagerLayer;
 use axum_session::{SessionConfig, SessionLayer, SessionStore};
 use axum_session_redispool::SessionRedisPool;
-use redis_pool::RedisPool;
-use secrecy::ExposeSecret;
+use redis_pool::{RedisPool, SingleRedisPool};
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, Connection};
 use sqlx::{Executor, PgConnection, PgPool, Pool, Postgres};
 use tower_http::trace::TraceLayer;
 use tower_sessions::{MemoryStore, SessionManagerLayer};
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    authentication::init_dummy_password_hash,
+    configuration::{
+        DatabaseSettings, LoginSettings, PasswordPolicySettings, Settings, SubscriptionSettings,
+        WebhookSettings,
+    },
     controller::{
-        admin_dashboard, change_password, change_password_form, confirm, health, home, login,
-        login_form, logout, publish_newsletter, publish_newsletter_form, subscribe,
+        accept_invitation, accept_invitation_form, admin_dashboard, cancel_newsletter_issue,
+        change_email, change_email_form, change_password, change_password_form,
+        change_subscriber_email, confirm, confirm_subscriber_manually, create_mailing_list,
+        deactivate_user, delete_subscriber, delivery_failures, disable_two_factor_authentication,
+        edit_draft_form, enable_two_factor_authentication, erase_subscriber_data,
+        erase_subscriber_data_form, export_subscriber_data, export_subscribers, health, home,
+        import_subscribers, import_subscribers_form, invite_user, login, login_form, logout,
+        mailing_lists, newsletter_issue, password_reset_form, password_reset_request_form,
+        postmark_webhook, preferences_form, preview_draft, publish_newsletter,
+        publish_newsletter_form, reactivate_user, request_data_links, request_password_reset,
+        reschedule_newsletter_issue, reset_password, save_draft, send_test_email, subscribe,
+        subscriber_consent, subscribers, subscription_challenge, suppress_subscriber,
+        two_factor_form, two_factor_settings, unsubscribe, unsubscribe_form, update_preferences,
+        users, verify_two_factor,
     },
+    domain::Role,
     email_client::EmailClient,
-    middleware::{auth_middleware, request_id_middleware, Zero2prodRequestId},
+    middleware::{auth_middleware, request_id_middleware, require_role, Zero2prodRequestId},
     view_engine::TeraView,
     Result,
 };
@@ -32,6 +51,13 @@ pub struct AppState {
     pub db_pool: Arc<Pool<Postgres>>,
     pub email_client: Arc<EmailClient>,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub behind_proxy: bool,
+    pub webhook: WebhookSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub login: LoginSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub redis_pool: SingleRedisPool,
     pub tera_engine: Arc<TeraView>,
 }
 
@@ -43,26 +69,114 @@ impl AppState {
                 .connect_lazy_with(configuration.database.with_db()),
         );
 
+        init_dummy_password_hash();
         let email_client = Arc::new(configuration.email_client.clone().client());
         let tera_engine = Arc::new(TeraView::build().expect("Failed to init tera view engine"));
+        let redis_client = redis::Client::open(configuration.redis_uri.expose_secret().as_str())
+            .expect("Failed when trying to open the redis connection");
         Self {
             db_pool,
             email_client,
             base_url: configuration.application.base_url.clone(),
+            hmac_secret: configuration.application.hmac_secret.clone(),
+            behind_proxy: configuration.application.behind_proxy,
+            webhook: configuration.email_client.webhook.clone(),
+            subscriptions: configuration.subscriptions.clone(),
+            login: configuration.login.clone(),
+            password_policy: configuration.password_policy.clone(),
+            redis_pool: SingleRedisPool::from(redis_client),
             tera_engine,
         }
     }
 }
 
-fn admin_routers() -> Router<AppState> {
+/// Subscriber CSV uploads are allowed past axum's default 2 MB body limit.
+const MAX_IMPORT_SIZE: usize = 20 * 1024 * 1024;
+
+/// Pages every admin user can see: their own account and the newsletter
+/// statistics.
+fn viewer_routers() -> Router<AppState> {
     Router::new()
         .route("/dashboard", get(admin_dashboard))
         .route("/password", get(change_password_form))
         .route("/password", post(change_password))
+        .route("/email", get(change_email_form))
+        .route("/email", post(change_email))
+        .route("/two-factor", get(two_factor_settings))
+        .route("/two-factor", post(enable_two_factor_authentication))
+        .route(
+            "/two-factor/disable",
+            post(disable_two_factor_authentication),
+        )
         .route("/logout", post(logout))
+        .route("/lists", get(mailing_lists))
         .route("/newsletters", get(publish_newsletter_form))
+}
+
+fn editor_routers() -> Router<AppState> {
+    Router::new()
+        .route("/lists", post(create_mailing_list))
         .route("/newsletters", post(publish_newsletter))
-        .route_layer(axum::middleware::from_fn(auth_middleware))
+        // Both list subscriber addresses, which viewers don't get to see.
+        .route("/newsletters/failures", get(delivery_failures))
+        .route("/newsletters/:newsletter_issue_id", get(newsletter_issue))
+        .route("/newsletters/drafts", post(save_draft))
+        .route("/newsletters/drafts/:draft_id", get(edit_draft_form))
+        .route("/newsletters/drafts/:draft_id/preview", get(preview_draft))
+        .route("/newsletters/drafts/:draft_id/test", post(send_test_email))
+        .route(
+            "/newsletters/:newsletter_issue_id/cancel",
+            post(cancel_newsletter_issue),
+        )
+        .route(
+            "/newsletters/:newsletter_issue_id/reschedule",
+            post(reschedule_newsletter_issue),
+        )
+        .route_layer(from_fn(|request, next| {
+            require_role(Role::Editor, request, next)
+        }))
+}
+
+fn owner_routers() -> Router<AppState> {
+    Router::new()
+        .route("/subscribers", get(subscribers))
+        .route("/subscribers/export", get(export_subscribers))
+        .route("/subscribers/import", get(import_subscribers_form))
+        .route(
+            "/subscribers/import",
+            post(import_subscribers).layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+        )
+        .route(
+            "/subscribers/:subscriber_id/consent",
+            get(subscriber_consent),
+        )
+        .route(
+            "/subscribers/:subscriber_id/confirm",
+            post(confirm_subscriber_manually),
+        )
+        .route(
+            "/subscribers/:subscriber_id/suppress",
+            post(suppress_subscriber),
+        )
+        .route(
+            "/subscribers/:subscriber_id/delete",
+            post(delete_subscriber),
+        )
+        .route("/users", get(users))
+        .route("/users/invitations", post(invite_user))
+        .route("/users/:user_id/deactivate", post(deactivate_user))
+        .route("/users/:user_id/reactivate", post(reactivate_user))
+        .route_layer(from_fn(|request, next| {
+            require_role(Role::Owner, request, next)
+        }))
+}
+
+fn admin_routers(state: AppState) -> Router<AppState> {
+    Router::new()
+        .merge(viewer_routers())
+        .merge(editor_routers())
+        .merge(owner_routers())
+        .route_layer(from_fn_with_state(state, auth_middleware))
 }
 
 pub fn app(state: AppState) -> Router {
@@ -71,9 +185,31 @@ pub fn app(state: AppState) -> Router {
         .route("/home", get(home))
         .route("/login", get(login_form))
         .route("/login", post(login))
+        .route("/login/forgot", get(password_reset_request_form))
+        .route("/login/forgot", post(request_password_reset))
+        .route("/login/reset", get(password_reset_form))
+        .route("/login/reset", post(reset_password))
+        .route("/login/two-factor", get(two_factor_form))
+        .route("/login/two-factor", post(verify_two_factor))
         .route("/subscriptions", post(subscribe))
+        .route("/subscriptions/challenge", get(subscription_challenge))
         .route("/subscriptions/confirm", get(confirm))
-        .nest("/admin", admin_routers())
+        .route("/subscriptions/unsubscribe", get(unsubscribe_form))
+        .route("/subscriptions/unsubscribe", post(unsubscribe))
+        .route("/subscriptions/preferences", get(preferences_form))
+        .route("/subscriptions/preferences", post(update_preferences))
+        .route(
+            "/subscriptions/preferences/email",
+            post(change_subscriber_email),
+        )
+        .route("/subscriptions/data", get(export_subscriber_data))
+        .route("/subscriptions/data", post(request_data_links))
+        .route("/subscriptions/data/erase", get(erase_subscriber_data_form))
+        .route("/subscriptions/data/erase", post(erase_subscriber_data))
+        .route("/invitations/accept", get(accept_invitation_form))
+        .route("/invitations/accept", post(accept_invitation))
+        .route("/webhooks/postmark", post(postmark_webhook))
+        .nest("/admin", admin_routers(state.clone()))
         .with_state(state)
 }
 
@@ -81,7 +217,11 @@ pub async fn run_until_stopped(state: AppState, configuration: Settings) -> Resu
     let app = register_layer(app(state), &configuration).await;
 
     let listener = tokio::net::TcpListener::bind(configuration.application.address()).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
 
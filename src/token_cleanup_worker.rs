@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+
+use crate::{
+    configuration::{Settings, SubscriptionSettings},
+    Result,
+};
+
+pub async fn run_token_cleanup_until_stopped(configuration: Settings) -> Result<()> {
+    let connection_pool = PgPoolOptions::new()
+        .acquire_timeout(std::time::Duration::from_secs(2))
+        .connect_lazy_with(configuration.database.with_db());
+    cleanup_loop(connection_pool, configuration.subscriptions).await
+}
+
+async fn cleanup_loop(pool: PgPool, settings: SubscriptionSettings) -> Result<()> {
+    loop {
+        match purge_expired_tokens(&pool, &settings).await {
+            Ok(n_purged) if n_purged > 0 => {
+                tracing::info!("Purged {} expired subscription tokens", n_purged);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to purge expired subscription tokens",
+                );
+            }
+        }
+        if let Err(e) = purge_signup_attempts(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to purge old signup attempts",
+            );
+        }
+        if let Err(e) = purge_proof_of_work_redemptions(&pool, &settings).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to purge old proof of work redemptions",
+            );
+        }
+        if let Err(e) = purge_password_reset_tokens(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to purge old password reset tokens",
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+/// Deletes confirmation tokens old enough that nobody should still be
+/// clicking them, returning how many were removed.
+pub async fn purge_expired_tokens(pool: &PgPool, settings: &SubscriptionSettings) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM subscription_tokens WHERE created_at < $1
+        "#,
+    )
+    .bind(Utc::now() - settings.token_purge_age())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Signup attempts only count towards the hourly rate limits.
+pub async fn purge_signup_attempts(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM signup_attempts WHERE attempted_at < now() - interval '1 hour'
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Challenges are rejected as expired once their time to live has passed,
+/// so there is no need to remember them as used any longer.
+pub async fn purge_proof_of_work_redemptions(
+    pool: &PgPool,
+    settings: &SubscriptionSettings,
+) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM proof_of_work_redemptions WHERE redeemed_at < $1
+        "#,
+    )
+    .bind(Utc::now() - settings.proof_of_work_ttl())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Reset links are long expired after a day, used or not.
+pub async fn purge_password_reset_tokens(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM password_reset_tokens WHERE created_at < now() - interval '1 day'
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
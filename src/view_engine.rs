@@ -6,6 +6,12 @@ use crate::{errors::Error, Result};
 
 const VIEWS_DIR: &str = "assets/views";
 
+/// The two bodies of an email rendered by [`TeraView::render_email`].
+pub struct EmailBody {
+    pub html: String,
+    pub text: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct TeraView {
     pub tera: tera::Tera,
@@ -28,7 +34,7 @@ impl TeraView {
         let tera = tera::Tera::new(
             path.as_ref()
                 .join("**")
-                .join("*.html")
+                .join("*.{html,txt}")
                 .to_str()
                 .ok_or_else(|| Error::string("invalid blob"))?,
         )?;
@@ -43,4 +49,18 @@ impl TeraView {
         let context = tera::Context::from_serialize(data)?;
         Ok(self.tera.render(key, &context)?)
     }
+
+    /// Renders the `emails/<name>.html` and `emails/<name>.txt` templates
+    /// with the same data.
+    pub fn render_email<S: Serialize>(&self, name: &str, data: S) -> Result<EmailBody> {
+        let context = tera::Context::from_serialize(data)?;
+        Ok(EmailBody {
+            html: self
+                .tera
+                .render(&format!("emails/{}.html", name), &context)?,
+            text: self
+                .tera
+                .render(&format!("emails/{}.txt", name), &context)?,
+        })
+    }
 }
@@ -0,0 +1,80 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_response_redirect_to, spawn_app, TestApp};
+
+async fn fetch_email(test_app: &TestApp) -> Option<String> {
+    let (email,): (Option<String>,) = sqlx::query_as("SELECT email FROM users WHERE user_id = $1")
+        .bind(test_app.test_user.user_id)
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    email
+}
+
+#[tokio::test]
+async fn changing_the_email_requires_the_current_password() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/email",
+            &serde_json::json!({
+                "email": "attacker@example.com",
+                "current_password": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+
+    assert_response_redirect_to(response, "/admin/email");
+    assert_eq!(
+        fetch_email(&test_app).await.as_deref(),
+        Some(test_app.test_user.email.as_str())
+    );
+}
+
+#[tokio::test]
+async fn the_old_address_is_told_about_an_email_change() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/email",
+            &serde_json::json!({
+                "email": "ursula@example.com",
+                "current_password": test_app.test_user.password,
+            }),
+            &cookie,
+        )
+        .await;
+
+    assert_response_redirect_to(response, "/admin/email");
+    assert_eq!(
+        fetch_email(&test_app).await.as_deref(),
+        Some("ursula@example.com")
+    );
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], test_app.test_user.email);
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("ursula@example.com"));
+}
@@ -0,0 +1,323 @@
+use axum::{body::Body, http::Request};
+use chrono::{DateTime, Utc};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::helpers::{
+    assert_response_redirect_to, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn insert_subscriber(
+    test_app: &TestApp,
+    email: &str,
+    status: &str,
+    subscribed_at: DateTime<Utc>,
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Subscriber', $3, $4)
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(email)
+    .bind(subscribed_at)
+    .bind(status)
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn fetch_status(test_app: &TestApp, subscriber_id: Uuid) -> Option<String> {
+    sqlx::query_as::<_, (String,)>("SELECT status FROM subscriptions WHERE id = $1")
+        .bind(subscriber_id)
+        .fetch_optional(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap()
+        .map(|row| row.0)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    insert_subscriber(&test_app, "ursula@example.com", "confirmed", Utc::now()).await;
+    insert_subscriber(&test_app, "octavia@example.com", "confirmed", Utc::now()).await;
+
+    let html = test_app
+        .get_html_with_cookie("/admin/subscribers?q=URSULA", &cookie)
+        .await;
+
+    assert!(html.contains("ursula@example.com"));
+    assert!(!html.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_signup_date() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let old = "2024-01-15T10:00:00Z".parse().unwrap();
+    let recent = "2024-06-15T10:00:00Z".parse().unwrap();
+    insert_subscriber(&test_app, "old@example.com", "confirmed", old).await;
+    insert_subscriber(&test_app, "recent@example.com", "confirmed", recent).await;
+    insert_subscriber(
+        &test_app,
+        "pending@example.com",
+        "pending_confirmation",
+        recent,
+    )
+    .await;
+
+    let html = test_app
+        .get_html_with_cookie(
+            "/admin/subscribers?q=&status=confirmed&from=2024-06-01&to=2024-06-15",
+            &cookie,
+        )
+        .await;
+
+    assert!(html.contains("recent@example.com"));
+    assert!(!html.contains("old@example.com"));
+    assert!(!html.contains("pending@example.com"));
+}
+
+#[tokio::test]
+async fn an_unknown_status_filter_is_rejected() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers?status=vip")
+                .header(axum::http::header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let now = Utc::now();
+    for i in 0..55 {
+        let subscribed_at = now - chrono::Duration::minutes(i);
+        insert_subscriber(
+            &test_app,
+            &format!("subscriber{:02}@example.com", i),
+            "confirmed",
+            subscribed_at,
+        )
+        .await;
+    }
+
+    let first_page = test_app
+        .get_html_with_cookie("/admin/subscribers?status=confirmed", &cookie)
+        .await;
+    assert!(first_page.contains("subscriber00@example.com"));
+    assert!(first_page.contains("subscriber49@example.com"));
+    assert!(!first_page.contains("subscriber50@example.com"));
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains("status=confirmed"));
+
+    let second_page = test_app
+        .get_html_with_cookie("/admin/subscribers?status=confirmed&page=2", &cookie)
+        .await;
+    assert!(second_page.contains("subscriber54@example.com"));
+    assert!(!second_page.contains("subscriber49@example.com"));
+}
+
+#[tokio::test]
+async fn a_page_number_too_large_to_offset_is_rejected() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/subscribers?page={}", i64::MAX))
+                .header(axum::http::header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn admins_can_confirm_a_pending_subscriber() {
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(test_app.app().await, &test_app).await;
+    let (subscriber_id,): (Uuid,) = sqlx::query_as("SELECT id FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &format!("/admin/subscribers/{}/confirm", subscriber_id),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    assert_response_redirect_to(response, "/admin/subscribers");
+    assert_eq!(
+        fetch_status(&test_app, subscriber_id).await.as_deref(),
+        Some("confirmed")
+    );
+}
+
+#[tokio::test]
+async fn a_manual_confirmation_is_recorded_as_an_admin_override() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let subscriber_id = insert_subscriber(
+        &test_app,
+        "ursula@example.com",
+        "pending_confirmation",
+        Utc::now(),
+    )
+    .await;
+
+    test_app
+        .post_form_with_cookie(
+            &format!("/admin/subscribers/{}/confirm", subscriber_id),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    let (event_type, source): (String, String) =
+        sqlx::query_as("SELECT event_type, source FROM consent_events WHERE subscriber_id = $1")
+            .bind(subscriber_id)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(event_type, "admin_override");
+    assert_eq!(source, "admin");
+}
+
+#[tokio::test]
+async fn admins_cannot_confirm_unsubscribed_or_suppressed_subscribers() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    for status in ["unsubscribed", "suppressed"] {
+        let email = format!("{}@example.com", status);
+        let subscriber_id = insert_subscriber(&test_app, &email, status, Utc::now()).await;
+
+        let response = test_app
+            .post_form_with_cookie(
+                &format!("/admin/subscribers/{}/confirm", subscriber_id),
+                &serde_json::json!({}),
+                &cookie,
+            )
+            .await;
+
+        assert_response_redirect_to(response, "/admin/subscribers");
+        assert_eq!(
+            fetch_status(&test_app, subscriber_id).await.as_deref(),
+            Some(status)
+        );
+        let (n_events,): (i64,) =
+            sqlx::query_as("SELECT count(*) FROM consent_events WHERE subscriber_id = $1")
+                .bind(subscriber_id)
+                .fetch_one(test_app.app_state.db_pool.as_ref())
+                .await
+                .unwrap();
+        assert_eq!(n_events, 0);
+    }
+}
+
+#[tokio::test]
+async fn admins_can_suppress_a_subscriber() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let subscriber_id =
+        insert_subscriber(&test_app, "ursula@example.com", "confirmed", Utc::now()).await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &format!("/admin/subscribers/{}/suppress", subscriber_id),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    assert_response_redirect_to(response, "/admin/subscribers");
+    assert_eq!(
+        fetch_status(&test_app, subscriber_id).await.as_deref(),
+        Some("suppressed")
+    );
+}
+
+#[tokio::test]
+async fn admins_can_delete_a_subscriber_with_tokens() {
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(test_app.app().await, &test_app).await;
+    let (subscriber_id,): (Uuid,) = sqlx::query_as("SELECT id FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &format!("/admin/subscribers/{}/delete", subscriber_id),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    assert_response_redirect_to(response, "/admin/subscribers");
+    assert_eq!(fetch_status(&test_app, subscriber_id).await, None);
+}
+
+#[tokio::test]
+async fn actions_on_an_unknown_subscriber_are_not_found() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &format!("/admin/subscribers/{}/suppress", Uuid::new_v4()),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
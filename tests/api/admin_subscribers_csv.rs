@@ -0,0 +1,270 @@
+use axum::{body::Body, http::Request};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_response_redirect_to, spawn_app, TestApp};
+
+async fn fetch_subscribers(test_app: &TestApp) -> Vec<(String, String, String)> {
+    sqlx::query_as("SELECT email, name, status FROM subscriptions ORDER BY email")
+        .fetch_all(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap()
+}
+
+async fn body_text(response: axum::http::Response<Body>) -> String {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_or_export_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscribers_import("email,name\nursula@example.com,Ursula\n", true, "")
+        .await;
+    assert_response_redirect_to(response, "/login");
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers/export")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn valid_rows_are_imported_and_invalid_ones_reported_by_line() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let csv = "Name,Email\n\
+        Ursula,ursula@example.com\n\
+        Octavia,not-an-email\n\
+        ,nameless@example.com\n\
+        Ursula again,ursula@example.com\n\
+        Ted,ted@example.com\n";
+
+    let response = test_app.post_subscribers_import(csv, true, &cookie).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = body_text(response).await;
+    assert!(html.contains("2 subscribers imported"));
+    assert!(html.contains("<td>3</td>"));
+    assert!(html.contains("<td>4</td>"));
+    assert!(html.contains("Duplicate of line 2."));
+    assert_eq!(
+        fetch_subscribers(&test_app).await,
+        vec![
+            ("ted@example.com".into(), "Ted".into(), "confirmed".into()),
+            (
+                "ursula@example.com".into(),
+                "Ursula".into(),
+                "confirmed".into()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn imported_subscribers_join_the_default_list() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    test_app
+        .post_subscribers_import("email,name\nursula@example.com,Ursula\n", true, &cookie)
+        .await;
+
+    let (n_members,): (i64,) = sqlx::query_as(
+        "SELECT count(*) FROM list_memberships JOIN mailing_lists USING (list_id) \
+            WHERE is_default",
+    )
+    .fetch_one(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+    assert_eq!(n_members, 1);
+}
+
+#[tokio::test]
+async fn importing_an_address_twice_does_not_duplicate_it() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let csv = "email,name\nursula@example.com,Ursula\n";
+    test_app.post_subscribers_import(csv, true, &cookie).await;
+
+    let response = test_app.post_subscribers_import(csv, true, &cookie).await;
+
+    let html = body_text(response).await;
+    assert!(html.contains("0 subscribers imported, 1 already registered"));
+    assert_eq!(fetch_subscribers(&test_app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn pending_imports_are_sent_a_confirmation_email() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_subscribers_import(
+            "email,name\nursula@example.com,Ursula\nted@example.com,Ted\n",
+            false,
+            &cookie,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(body_text(response)
+        .await
+        .contains("2 confirmation emails queued"));
+    // The emails go out after the response.
+    for _ in 0..50 {
+        if test_app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .len()
+            == 2
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let statuses = fetch_subscribers(&test_app)
+        .await
+        .into_iter()
+        .map(|(_, _, status)| status)
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, vec!["pending_confirmation"; 2]);
+}
+
+#[tokio::test]
+async fn a_file_without_an_email_column_is_rejected() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .post_subscribers_import("address,name\nursula@example.com,Ursula\n", true, &cookie)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(fetch_subscribers(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_export_is_a_csv_of_the_filtered_subscribers() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    for (email, status) in [
+        ("ursula@example.com", "confirmed"),
+        ("ted@example.com", "pending_confirmation"),
+    ] {
+        sqlx::query(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+                VALUES ($1, $2, 'Subscriber', now(), $3)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(email)
+        .bind(status)
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    }
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers/export?status=confirmed")
+                .header(axum::http::header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let csv = body_text(response).await;
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("email,name,status,subscribed_at"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("ursula@example.com,Subscriber,confirmed,"));
+    assert_eq!(lines.next(), None);
+}
+
+#[tokio::test]
+async fn the_export_escapes_cells_a_spreadsheet_would_run_as_formulas() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    sqlx::query(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+            VALUES ($1, 'ursula@example.com', $2, now(), 'confirmed')",
+    )
+    .bind(Uuid::new_v4())
+    .bind("=HYPERLINK(\"https://example.com\")")
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers/export")
+                .header(axum::http::header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let csv = body_text(response).await;
+    let row = csv.lines().nth(1).unwrap();
+    assert!(row
+        .starts_with("ursula@example.com,\"'=HYPERLINK(\"\"https://example.com\"\")\",confirmed,"));
+}
+
+#[tokio::test]
+async fn the_export_rejects_an_unknown_status() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/admin/subscribers/export?status=vip")
+                .header(axum::http::header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
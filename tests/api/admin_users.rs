@@ -0,0 +1,303 @@
+use axum::{
+    body::Body,
+    http::{header, Request},
+};
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_response_redirect_to, path_and_query, spawn_app, TestApp, TestUser};
+
+async fn store_user(test_app: &TestApp, role: &str) -> TestUser {
+    let user = TestUser::with_role(role);
+    user.store(&test_app.app_state.db_pool).await;
+    user
+}
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+/// Invites `email` as the test user and returns the link from the invitation.
+async fn invite(test_app: &TestApp, email: &str, role: &str) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/users/invitations",
+            &serde_json::json!({"email": email, "role": role}),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/users");
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    path_and_query(test_app.get_confirmation_links(email_request).plain_text)
+}
+
+async fn get_status(test_app: &TestApp, uri: &str, cookie: &str) -> u16 {
+    test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn viewers_can_read_statistics_but_not_publish() {
+    let test_app = spawn_app().await;
+    let viewer = store_user(&test_app, "viewer").await;
+    let cookie = test_app.login_as_and_get_cookie(&viewer).await;
+
+    assert_eq!(
+        get_status(&test_app, "/admin/newsletters", &cookie).await,
+        200
+    );
+
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter(), &cookie)
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/lists",
+            &serde_json::json!({"name": "Weekly"}),
+            &cookie,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_cannot_see_subscriber_addresses() {
+    let test_app = spawn_app().await;
+    let viewer = store_user(&test_app, "viewer").await;
+    let cookie = test_app.login_as_and_get_cookie(&viewer).await;
+
+    assert_eq!(
+        get_status(&test_app, "/admin/newsletters/failures", &cookie).await,
+        403
+    );
+    assert_eq!(
+        get_status(
+            &test_app,
+            &format!("/admin/newsletters/{}", Uuid::new_v4()),
+            &cookie
+        )
+        .await,
+        403
+    );
+}
+
+#[tokio::test]
+async fn editors_can_publish_but_not_manage_subscribers_or_users() {
+    let test_app = spawn_app().await;
+    let editor = store_user(&test_app, "editor").await;
+    let cookie = test_app.login_as_and_get_cookie(&editor).await;
+
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter(), &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+
+    assert_eq!(
+        get_status(&test_app, "/admin/subscribers", &cookie).await,
+        403
+    );
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/users/invitations",
+            &serde_json::json!({"email": "ursula_le_guin@gmail.com", "role": "owner"}),
+            &cookie,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_invited_user_can_set_up_their_account_and_log_in() {
+    let test_app = spawn_app().await;
+    let accept_path = invite(&test_app, "ursula_le_guin@gmail.com", "editor").await;
+
+    assert_eq!(get_status(&test_app, &accept_path, "").await, 200);
+    let response = test_app
+        .post_form_with_cookie(
+            &accept_path,
+            &serde_json::json!({
+                "username": "ursula",
+                "password": "a wizard of earthsea",
+                "password_check": "a wizard of earthsea",
+            }),
+            "",
+        )
+        .await;
+    assert_response_redirect_to(response, "/login");
+
+    let (email, role): (String, String) =
+        sqlx::query_as("SELECT email, role FROM users WHERE username = 'ursula'")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+    assert_eq!(role, "editor");
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": "ursula",
+            "password": "a wizard of earthsea",
+        }))
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn invited_users_must_pick_a_password_meeting_the_policy() {
+    let test_app = spawn_app().await;
+    let accept_path = invite(&test_app, "ursula_le_guin@gmail.com", "editor").await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &accept_path,
+            &serde_json::json!({
+                "username": "ursula",
+                "password": "earthsea",
+                "password_check": "earthsea",
+            }),
+            "",
+        )
+        .await;
+
+    assert_response_redirect_to(response, &accept_path);
+    let (n_users,): (i64,) = sqlx::query_as("SELECT count(*) FROM users WHERE username = 'ursula'")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(n_users, 0);
+    assert_eq!(get_status(&test_app, &accept_path, "").await, 200);
+}
+
+#[tokio::test]
+async fn invitations_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    let accept_path = invite(&test_app, "ursula_le_guin@gmail.com", "viewer").await;
+    let accept = |username: &str| {
+        serde_json::json!({
+            "username": username,
+            "password": "a wizard of earthsea",
+            "password_check": "a wizard of earthsea",
+        })
+    };
+    test_app
+        .post_form_with_cookie(&accept_path, &accept("ursula"), "")
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(&accept_path, &accept("ged"), "")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_status(&test_app, &accept_path, "").await, 401);
+}
+
+#[tokio::test]
+async fn expired_invitations_are_rejected() {
+    let test_app = spawn_app().await;
+    let accept_path = invite(&test_app, "ursula_le_guin@gmail.com", "viewer").await;
+    sqlx::query("UPDATE user_invitations SET created_at = now() - interval '8 days'")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    assert_eq!(get_status(&test_app, &accept_path, "").await, 401);
+}
+
+#[tokio::test]
+async fn unknown_invitation_tokens_are_rejected() {
+    let test_app = spawn_app().await;
+
+    assert_eq!(
+        get_status(&test_app, "/invitations/accept?token=notarealtoken", "").await,
+        401
+    );
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out_and_keep_their_idempotency_records() {
+    let test_app = spawn_app().await;
+    let editor = store_user(&test_app, "editor").await;
+    let editor_cookie = test_app.login_as_and_get_cookie(&editor).await;
+    test_app
+        .post_newsletter_with_cookie(&newsletter(), &editor_cookie)
+        .await;
+
+    let owner_cookie = test_app.login_and_get_cookie().await;
+    let response = test_app
+        .post_form_with_cookie(
+            &format!("/admin/users/{}/deactivate", editor.user_id),
+            &serde_json::json!({}),
+            &owner_cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/users");
+
+    let response = test_app
+        .get_admin_dashboard_with_cookie(&editor_cookie)
+        .await;
+    assert_response_redirect_to(response, "/login");
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": editor.username,
+            "password": editor.password,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/login");
+    let (n_records,): (i64,) =
+        sqlx::query_as("SELECT count(*) FROM idempotency WHERE user_id = $1")
+            .bind(editor.user_id)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(n_records, 1);
+}
+
+#[tokio::test]
+async fn owners_cannot_deactivate_themselves() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    test_app
+        .post_form_with_cookie(
+            &format!("/admin/users/{}/deactivate", test_app.test_user.user_id),
+            &serde_json::json!({}),
+            &cookie,
+        )
+        .await;
+
+    let (is_active,): (bool,) = sqlx::query_as("SELECT is_active FROM users WHERE user_id = $1")
+        .bind(test_app.test_user.user_id)
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert!(is_active);
+    let response = test_app.get_admin_dashboard_with_cookie(&cookie).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
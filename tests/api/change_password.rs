@@ -1,4 +1,9 @@
+use sha1::{Digest, Sha1};
 use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
 
 use crate::helpers::{assert_response_redirect_to, spawn_app};
 
@@ -61,3 +66,107 @@ pub async fn current_password_must_be_valid() {
         .await;
     assert_response_redirect_to(response, "/admin/password");
 }
+
+#[tokio::test]
+pub async fn changing_the_password_sends_a_notice_to_the_admin() {
+    let test_app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    let cookie = test_app.login_and_get_cookie().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "current_password": test_app.test_user.password,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    });
+    let response = test_app
+        .post_update_password_with_cookie(body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], test_app.test_user.email.as_str());
+    assert_eq!(email["Subject"], "Your password was changed");
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("Hi {},", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn new_passwords_must_meet_the_password_policy() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let username = test_app.test_user.username.clone();
+
+    for new_password in [
+        "short".to_string(),
+        "x".repeat(129),
+        "password1234".to_string(),
+        "qwertyuiop123456".to_string(),
+        format!("{}2024", username),
+    ] {
+        let body = serde_json::json!({
+            "current_password": test_app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        });
+        let response = test_app
+            .post_update_password_with_cookie(body, &cookie)
+            .await;
+        assert_response_redirect_to(response, "/admin/password");
+    }
+
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": username,
+            "password": test_app.test_user.password,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn breached_passwords_are_rejected() {
+    let mut test_app = spawn_app().await;
+    let breached_password = "correct horse battery staple";
+    let breached_passwords_file =
+        std::env::temp_dir().join(format!("breached-{}.txt", Uuid::new_v4()));
+    std::fs::write(
+        &breached_passwords_file,
+        format!(
+            "{}:3645804\n",
+            hex::encode_upper(Sha1::digest(breached_password))
+        ),
+    )
+    .unwrap();
+    test_app.app_state.password_policy.breached_passwords_file =
+        Some(breached_passwords_file.to_string_lossy().into_owned());
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let body = serde_json::json!({
+        "current_password": test_app.test_user.password,
+        "new_password": breached_password,
+        "new_password_check": breached_password,
+    });
+    let response = test_app
+        .post_update_password_with_cookie(body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/password");
+
+    let new_password = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "current_password": test_app.test_user.password,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    });
+    let response = test_app
+        .post_update_password_with_cookie(body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
@@ -0,0 +1,235 @@
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{self, Request},
+    Router,
+};
+use sqlx::prelude::FromRow;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::startup::app;
+
+use crate::helpers::{path_and_query, spawn_app, TestApp};
+
+#[derive(FromRow, Debug)]
+struct ConsentEvent {
+    event_type: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+}
+
+async fn fetch_consent_events(test_app: &TestApp) -> Vec<ConsentEvent> {
+    sqlx::query_as(
+        "SELECT event_type, source, ip_address, user_agent, request_id \
+            FROM consent_events ORDER BY occurred_at",
+    )
+    .fetch_all(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap()
+}
+
+/// Signs up from `peer`, the address the connection comes from.
+pub async fn post_subscriptions_from(
+    app: Router,
+    body: &str,
+    peer: &str,
+    headers: &[(&str, &str)],
+) -> http::Response<Body> {
+    let mut request = Request::builder()
+        .method(http::Method::POST)
+        .uri("/subscriptions")
+        .header(
+            http::header::CONTENT_TYPE,
+            mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+        )
+        .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    app.oneshot(request.body(Body::new(body.to_string())).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn mount_email_server(test_app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn signing_up_records_the_consent_evidence() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+
+    let response = post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&source=spring-campaign",
+        "203.0.113.7:51234",
+        &[("user-agent", "Mozilla/5.0"), ("x-request-id", "signup-42")],
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let events = fetch_consent_events(&test_app).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, "signup");
+    assert_eq!(events[0].source, "spring-campaign");
+    assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+    assert_eq!(events[0].user_agent.as_deref(), Some("Mozilla/5.0"));
+    assert_eq!(events[0].request_id.as_deref(), Some("signup-42"));
+}
+
+#[tokio::test]
+async fn the_signup_source_defaults_to_the_subscription_form() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+
+    post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "203.0.113.7:51234",
+        &[],
+    )
+    .await;
+
+    let events = fetch_consent_events(&test_app).await;
+    assert_eq!(events[0].source, "subscription_form");
+}
+
+#[tokio::test]
+async fn following_the_confirmation_link_records_the_confirmation() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "203.0.113.7:51234",
+        &[],
+    )
+    .await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+
+    test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri(path_and_query(confirmation_links.plain_text))
+                .extension(ConnectInfo(
+                    "198.51.100.23:40000".parse::<SocketAddr>().unwrap(),
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let events = fetch_consent_events(&test_app).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].event_type, "confirmation");
+    assert_eq!(events[1].source, "confirmation_link");
+    assert_eq!(events[1].ip_address.as_deref(), Some("198.51.100.23"));
+}
+
+#[tokio::test]
+async fn joining_another_list_records_the_consent_evidence() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let list_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO mailing_lists (list_id, name) VALUES ($1, 'Release notes')")
+        .bind(list_id)
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    for body in [
+        "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string(),
+        format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}&source=release-page",
+            list_id
+        ),
+    ] {
+        post_subscriptions_from(test_app.app().await, &body, "203.0.113.7:51234", &[]).await;
+        let email_request = test_app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let confirmation_links = test_app.get_confirmation_links(&email_request);
+        crate::subscriptions::get_confirmation(
+            test_app.app().await,
+            path_and_query(confirmation_links.plain_text),
+        )
+        .await;
+    }
+
+    let events = fetch_consent_events(&test_app).await;
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[2].event_type, "signup");
+    assert_eq!(events[2].source, "release-page");
+    assert_eq!(events[2].ip_address.as_deref(), Some("203.0.113.7"));
+    assert_eq!(events[3].event_type, "confirmation");
+    assert_eq!(events[3].source, "list_join");
+}
+
+#[tokio::test]
+async fn behind_a_proxy_the_last_forwarded_address_is_recorded() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let mut app_state = test_app.app_state.clone();
+    app_state.behind_proxy = true;
+    let router = test_app.register_layer(app(app_state)).await;
+
+    post_subscriptions_from(
+        router,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "10.0.0.2:51234",
+        &[("x-forwarded-for", "192.0.2.1, 203.0.113.7")],
+    )
+    .await;
+
+    let events = fetch_consent_events(&test_app).await;
+    assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn admins_can_see_the_consent_of_a_subscriber() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "203.0.113.7:51234",
+        &[],
+    )
+    .await;
+    let (subscriber_id,): (Uuid,) = sqlx::query_as("SELECT id FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let html = test_app
+        .get_html_with_cookie(
+            &format!("/admin/subscribers/{}/consent", subscriber_id),
+            &cookie,
+        )
+        .await;
+
+    assert!(html.contains("ursula_le_guin@gmail.com"));
+    assert!(html.contains("203.0.113.7"));
+    assert!(html.contains("subscription_form"));
+}
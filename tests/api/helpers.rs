@@ -4,6 +4,7 @@ use axum::{
     http::{self, HeaderValue, Request},
     Router,
 };
+use base64::{prelude::BASE64_STANDARD, Engine};
 use fake::{
     faker::{internet::en::SafeEmail, name::en::Name},
     Fake,
@@ -15,6 +16,7 @@ use hyper::{
 };
 use once_cell::sync::Lazy;
 use reqwest::Url;
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use tower::ServiceExt;
 use uuid::Uuid;
@@ -25,7 +27,7 @@ use wiremock::{
 use zero2prod::{
     configuration::{get_configuration, Settings},
     email_client::EmailClient,
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    issue_delivery_worker::{promote_scheduled_issues, try_execute_task, ExecutionOutcome},
     startup::{app, configuration_database, register_layer, AppState},
     telemetry::init,
 };
@@ -151,12 +153,138 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .expect("Missing List-Unsubscribe header.");
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let unsubscribe_link = Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link
+    }
+
+    pub async fn post_unsubscribe(&self, path_and_query: &str) -> http::Response<Body> {
+        self.app()
+            .await
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .header(
+                        http::header::CONTENT_TYPE,
+                        mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+                    )
+                    .uri(path_and_query)
+                    .body(Body::new("List-Unsubscribe=One-Click".to_string()))
+                    .unwrap(),
+            )
+            .await
+            .expect("Failed to execute request unsubscribe.")
+    }
+
+    pub async fn post_postmark_webhook(
+        &self,
+        body: &serde_json::Value,
+        credentials: Option<(&str, &str)>,
+    ) -> http::Response<Body> {
+        let mut request = Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .uri("/webhooks/postmark");
+        if let Some((username, password)) = credentials {
+            let encoded = BASE64_STANDARD.encode(format!("{}:{}", username, password));
+            request = request.header(header::AUTHORIZATION, format!("Basic {}", encoded));
+        }
+        self.app()
+            .await
+            .oneshot(request.body(Body::new(body.to_string())).unwrap())
+            .await
+            .expect("Failed to execute request postmark webhook.")
+    }
+
+    pub fn webhook_credentials(&self) -> (&str, &str) {
+        let webhook = &self.configuration.email_client.webhook;
+        (&webhook.username, webhook.password.expose_secret())
+    }
+
     pub async fn post_newsletter_with_cookie(
         &self,
         body: &serde_json::Value,
         cookie: &str,
+    ) -> http::Response<Body> {
+        self.post_form_with_cookie("/admin/newsletters", body, cookie)
+            .await
+    }
+
+    pub async fn post_cancel_newsletter_issue(
+        &self,
+        issue_id: Uuid,
+        cookie: &str,
+    ) -> http::Response<Body> {
+        self.post_form_with_cookie(
+            &format!("/admin/newsletters/{}/cancel", issue_id),
+            &serde_json::json!({}),
+            cookie,
+        )
+        .await
+    }
+
+    pub async fn post_reschedule_newsletter_issue(
+        &self,
+        issue_id: Uuid,
+        body: &serde_json::Value,
+        cookie: &str,
+    ) -> http::Response<Body> {
+        self.post_form_with_cookie(
+            &format!("/admin/newsletters/{}/reschedule", issue_id),
+            body,
+            cookie,
+        )
+        .await
+    }
+
+    pub async fn post_save_draft(
+        &self,
+        body: &serde_json::Value,
+        cookie: &str,
+    ) -> http::Response<Body> {
+        self.post_form_with_cookie("/admin/newsletters/drafts", body, cookie)
+            .await
+    }
+
+    pub async fn post_send_test_email(&self, draft_id: Uuid, cookie: &str) -> http::Response<Body> {
+        self.post_form_with_cookie(
+            &format!("/admin/newsletters/drafts/{}/test", draft_id),
+            &serde_json::json!({}),
+            cookie,
+        )
+        .await
+    }
+
+    pub async fn post_form_with_cookie(
+        &self,
+        uri: &str,
+        body: &serde_json::Value,
+        cookie: &str,
     ) -> http::Response<Body> {
         let body = serde_urlencoded::to_string(body).unwrap();
+        self.post_encoded_form_with_cookie(uri, body, cookie).await
+    }
+
+    /// For forms `serde_urlencoded` can't encode, like repeated fields.
+    pub async fn post_encoded_form_with_cookie(
+        &self,
+        uri: &str,
+        body: String,
+        cookie: &str,
+    ) -> http::Response<Body> {
         self.app()
             .await
             .oneshot(
@@ -167,12 +295,51 @@ impl TestApp {
                         mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
                     )
                     .header(header::COOKIE, cookie)
-                    .uri("/admin/newsletters")
+                    .uri(uri)
                     .body(Body::new(body.to_string()))
                     .unwrap(),
             )
             .await
-            .expect("Failed to execute request newsletters.")
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscribers_import(
+        &self,
+        csv: &str,
+        confirmed: bool,
+        cookie: &str,
+    ) -> http::Response<Body> {
+        let boundary = "zero2prod-test-boundary";
+        let mut body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n"
+        );
+        if confirmed {
+            body.push_str(&format!(
+                "--{boundary}\r\n\
+                Content-Disposition: form-data; name=\"confirmed\"\r\n\r\n\
+                true\r\n"
+            ));
+        }
+        body.push_str(&format!("--{boundary}--\r\n"));
+        self.app()
+            .await
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .header(
+                        http::header::CONTENT_TYPE,
+                        format!("multipart/form-data; boundary={}", boundary),
+                    )
+                    .header(header::COOKIE, cookie)
+                    .uri("/admin/subscribers/import")
+                    .body(Body::new(body))
+                    .unwrap(),
+            )
+            .await
+            .expect("Failed to execute request.")
     }
 
     pub async fn get_publish_newsletter(&self) -> http::Response<Body> {
@@ -214,6 +381,38 @@ impl TestApp {
         String::from_utf8(body.to_bytes().to_vec()).expect("Failed to parse body to string")
     }
 
+    pub async fn get_html_with_cookie(&self, uri: &str, cookie: &str) -> String {
+        let response = self
+            .app()
+            .await
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::GET)
+                    .header(header::COOKIE, cookie)
+                    .uri(uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .expect("Failed to execute request.");
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("Failed to collect body");
+        String::from_utf8(body.to_bytes().to_vec()).expect("Failed to parse body to string")
+    }
+
+    pub async fn get_delivery_failures_html(&self, cookie: &str) -> String {
+        self.get_html_with_cookie("/admin/newsletters/failures", cookie)
+            .await
+    }
+
+    pub async fn get_newsletter_issue_html(&self, issue_id: Uuid, cookie: &str) -> String {
+        self.get_html_with_cookie(&format!("/admin/newsletters/{}", issue_id), cookie)
+            .await
+    }
+
     pub async fn get_change_password_with_cookie(&self, cookie: &str) -> http::Response<Body> {
         self.app()
             .await
@@ -230,20 +429,35 @@ impl TestApp {
     }
 
     pub async fn login_and_get_cookie(&self) -> String {
+        self.login_as_and_get_cookie(&self.test_user).await
+    }
+
+    pub async fn login_as_and_get_cookie(&self, user: &TestUser) -> String {
         let body = serde_json::json!({
-            "username":self.test_user.username,
-            "password": self.test_user.password,
+            "username": user.username,
+            "password": user.password,
         });
         let response = self.post_login(body).await;
         get_cookie(response)
     }
 
+    pub async fn promote_scheduled_issues(&self) -> usize {
+        promote_scheduled_issues(&self.app_state.db_pool)
+            .await
+            .unwrap()
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.app_state.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.app_state.db_pool,
+                &self.email_client,
+                &self.app_state.base_url,
+                &self.app_state.hmac_secret,
+                &self.configuration.issue_delivery,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -289,14 +503,22 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub email: String,
+    pub role: String,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::with_role("owner")
+    }
+
+    pub fn with_role(role: &str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: "everythinghastostartsomewhere".into(),
+            email: SafeEmail().fake(),
+            role: role.into(),
         }
     }
 
@@ -309,13 +531,15 @@ impl TestUser {
         dbg!(&password_hash);
         sqlx::query(
             r#"
-            INSERT INTO users(user_id, username, password_hash)
-            VALUES ($1, $2, $3)
+            INSERT INTO users(user_id, username, password_hash, email, role)
+            VALUES ($1, $2, $3, $4, $5)
             "#,
         )
         .bind(self.user_id)
         .bind(self.username.clone())
         .bind(password_hash)
+        .bind(self.email.clone())
+        .bind(self.role.clone())
         .execute(pool)
         .await
         .expect("Failed to store test user.");
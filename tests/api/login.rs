@@ -1,4 +1,61 @@
-use crate::helpers::{assert_response_redirect_to, spawn_app};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{self, Request},
+    Router,
+};
+use tower::ServiceExt;
+use uuid::Uuid;
+use zero2prod::{configuration::LoginSettings, startup::app};
+
+use crate::helpers::{assert_response_redirect_to, spawn_app, TestApp};
+
+/// Logs in from `peer`, the address the connection comes from.
+async fn post_login_from(
+    router: Router,
+    username: &str,
+    password: &str,
+    peer: &str,
+) -> http::Response<Body> {
+    let body = serde_urlencoded::to_string([("username", username), ("password", password)]);
+    router
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/login")
+                .header(
+                    http::header::CONTENT_TYPE,
+                    mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+                )
+                .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()))
+                .body(Body::new(body.unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+/// Counters live in Redis and outlast the test database, so every test logs
+/// in from an address of its own.
+fn random_peer() -> String {
+    let bytes = Uuid::new_v4().into_bytes();
+    format!("10.{}.{}.{}:51234", bytes[0], bytes[1], bytes[2])
+}
+
+/// An app with strict limits and no delays, unless a test asks for them.
+async fn throttled_app(test_app: &TestApp, configure: impl FnOnce(&mut LoginSettings)) -> Router {
+    let mut app_state = test_app.app_state.clone();
+    app_state.login.max_failures_per_username = 3;
+    app_state.login.max_failures_per_ip = 5;
+    app_state.login.failures_before_delay = 100;
+    configure(&mut app_state.login);
+    test_app.register_layer(app(app_state)).await
+}
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -32,3 +89,128 @@ async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
     assert_eq!(response.status().as_u16(), 303);
     assert_response_redirect_to(response, "/login");
 }
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    let test_app = spawn_app().await;
+    let router = throttled_app(&test_app, |_| {}).await;
+    let user = &test_app.test_user;
+
+    for _ in 0..3 {
+        let response = post_login_from(
+            router.clone(),
+            &user.username,
+            "wrong-password",
+            &random_peer(),
+        )
+        .await;
+        assert_response_redirect_to(response, "/login");
+    }
+    // Even the right password, from another address, is turned away.
+    let response = post_login_from(router, &user.username, &user.password, &random_peer()).await;
+
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn a_client_address_is_locked_out_after_too_many_failures() {
+    let test_app = spawn_app().await;
+    let router = throttled_app(&test_app, |_| {}).await;
+    let peer = random_peer();
+
+    for _ in 0..5 {
+        post_login_from(
+            router.clone(),
+            &Uuid::new_v4().to_string(),
+            "wrong-password",
+            &peer,
+        )
+        .await;
+    }
+    let user = &test_app.test_user;
+    let response = post_login_from(router.clone(), &user.username, &user.password, &peer).await;
+    assert_response_redirect_to(response, "/login");
+
+    let response = post_login_from(router, &user.username, &user.password, &random_peer()).await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_successful_login_forgives_earlier_failures() {
+    let test_app = spawn_app().await;
+    let router = throttled_app(&test_app, |_| {}).await;
+    let user = &test_app.test_user;
+
+    for _ in 0..2 {
+        for _ in 0..2 {
+            post_login_from(
+                router.clone(),
+                &user.username,
+                "wrong-password",
+                &random_peer(),
+            )
+            .await;
+        }
+        let response = post_login_from(
+            router.clone(),
+            &user.username,
+            &user.password,
+            &random_peer(),
+        )
+        .await;
+        assert_response_redirect_to(response, "/admin/dashboard");
+    }
+}
+
+#[tokio::test]
+async fn repeated_failures_are_answered_more_and_more_slowly() {
+    let test_app = spawn_app().await;
+    let router = throttled_app(&test_app, |login| {
+        login.max_failures_per_username = 10;
+        login.failures_before_delay = 1;
+        login.base_delay_milliseconds = 200;
+    })
+    .await;
+    let username = Uuid::new_v4().to_string();
+
+    let mut durations = vec![];
+    for _ in 0..3 {
+        let start = Instant::now();
+        post_login_from(router.clone(), &username, "wrong-password", &random_peer()).await;
+        durations.push(start.elapsed());
+    }
+
+    assert!(durations[1] >= Duration::from_millis(200));
+    assert!(durations[2] >= Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn concurrent_guesses_cannot_get_past_the_limit() {
+    let test_app = spawn_app().await;
+    // Every guess that gets checked is answered a second late, so guesses
+    // answered sooner were turned away unchecked.
+    let router = throttled_app(&test_app, |login| {
+        login.failures_before_delay = 0;
+        login.base_delay_milliseconds = 1000;
+        login.max_delay_milliseconds = 1000;
+    })
+    .await;
+    let username = test_app.test_user.username.clone();
+
+    let durations = futures_util::future::join_all((0..8).map(|_| {
+        let router = router.clone();
+        let username = username.clone();
+        async move {
+            let start = Instant::now();
+            post_login_from(router, &username, "wrong-password", &random_peer()).await;
+            start.elapsed()
+        }
+    }))
+    .await;
+
+    let n_checked = durations
+        .iter()
+        .filter(|duration| **duration >= Duration::from_millis(1000))
+        .count();
+    assert_eq!(n_checked, 3);
+}
@@ -0,0 +1,292 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{assert_response_redirect_to, path_and_query, spawn_app, TestApp},
+    subscriptions::post_subscriptions,
+};
+
+async fn create_list(test_app: &TestApp, name: &str, cookie: &str) -> Uuid {
+    let response = test_app
+        .post_form_with_cookie("/admin/lists", &serde_json::json!({"name": name}), cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/lists");
+    let row: (Uuid,) = sqlx::query_as("SELECT list_id FROM mailing_lists WHERE name = $1")
+        .bind(name)
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    row.0
+}
+
+async fn list_ids_of(test_app: &TestApp, email: &str) -> Vec<Uuid> {
+    sqlx::query_as::<_, (Uuid,)>(
+        r#"
+        SELECT m.list_id
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1
+        ORDER BY m.joined_at
+        "#,
+    )
+    .bind(email)
+    .fetch_all(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.0)
+    .collect()
+}
+
+async fn default_list_id(test_app: &TestApp) -> Uuid {
+    let row: (Uuid,) = sqlx::query_as("SELECT list_id FROM mailing_lists WHERE is_default")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    row.0
+}
+
+/// Subscribes `email` to `list_id` and follows the confirmation link, if one
+/// is sent.
+async fn subscribe_to(test_app: &TestApp, email: &str, list_id: Uuid) {
+    let app = test_app.app().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let n_requests = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .len();
+    let body = format!("name=le%20guin&email={}&list_id={}", email, list_id);
+    let response = post_subscriptions(app.clone(), &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let requests = test_app.email_server.received_requests().await.unwrap();
+    if requests.len() > n_requests {
+        let links = test_app.get_confirmation_links(&requests[n_requests]);
+        crate::subscriptions::get_confirmation(app, path_and_query(links.plain_text)).await;
+    }
+}
+
+async fn publish_to(test_app: &TestApp, list_ids: &[Uuid], cookie: &str) {
+    let mut body = serde_urlencoded::to_string(serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .unwrap();
+    for list_id in list_ids {
+        body.push_str(&format!("&list_id={}", list_id));
+    }
+    let response = test_app
+        .post_encoded_form_with_cookie("/admin/newsletters", body, cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn an_admin_can_create_a_mailing_list() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    create_list(&test_app, "Release notes", &cookie).await;
+
+    let html = test_app.get_html_with_cookie("/admin/lists", &cookie).await;
+    assert!(html.contains("Release notes"));
+}
+
+#[tokio::test]
+async fn subscribing_without_a_list_joins_the_default_list() {
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = post_subscriptions(test_app.app().await, body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        list_ids_of(&test_app, "ursula_le_guin@gmail.com").await,
+        vec![default_list_id(&test_app).await]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_with_a_list_id_joins_that_list() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", list_id).await;
+
+    assert_eq!(
+        list_ids_of(&test_app, "ursula_le_guin@gmail.com").await,
+        vec![list_id]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        Uuid::new_v4()
+    );
+    let response = post_subscriptions(test_app.app().await, &body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_can_join_more_lists() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let default_list_id = default_list_id(&test_app).await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", default_list_id).await;
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", list_id).await;
+
+    assert_eq!(
+        list_ids_of(&test_app, "ursula_le_guin@gmail.com").await,
+        vec![default_list_id, list_id]
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_only_join_another_list_once_they_confirm() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let default_list_id = default_list_id(&test_app).await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", default_list_id).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let n_requests = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .len();
+
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        list_id
+    );
+    let response = post_subscriptions(test_app.app().await, &body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        list_ids_of(&test_app, "ursula_le_guin@gmail.com").await,
+        vec![default_list_id]
+    );
+    let requests = test_app.email_server.received_requests().await.unwrap();
+    let links = test_app.get_confirmation_links(&requests[n_requests]);
+    crate::subscriptions::get_confirmation(test_app.app().await, path_and_query(links.plain_text))
+        .await;
+    assert_eq!(
+        list_ids_of(&test_app, "ursula_le_guin@gmail.com").await,
+        vec![default_list_id, list_id]
+    );
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_members_of_the_targeted_lists() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let default_list_id = default_list_id(&test_app).await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", default_list_id).await;
+    subscribe_to(&test_app, "octavia_butler@gmail.com", list_id).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_to(&test_app, &[list_id], &cookie).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let requests = test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "octavia_butler@gmail.com");
+}
+
+#[tokio::test]
+async fn members_of_several_targeted_lists_receive_the_issue_once() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let default_list_id = default_list_id(&test_app).await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", default_list_id).await;
+    subscribe_to(&test_app, "ursula_le_guin@gmail.com", list_id).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_to(&test_app, &[default_list_id, list_id], &cookie).await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let mut body = serde_urlencoded::to_string(serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .unwrap();
+    body.push_str(&format!("&list_id={}", Uuid::new_v4()));
+
+    let response = test_app
+        .post_encoded_form_with_cookie("/admin/newsletters", body, &cookie)
+        .await;
+
+    assert_response_redirect_to(response, "/admin/newsletters");
+    let (n_issues,): (i64,) = sqlx::query_as("SELECT count(*) FROM newsletter_issues")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn a_list_picked_twice_is_targeted_once() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let list_id = create_list(&test_app, "Release notes", &cookie).await;
+
+    publish_to(&test_app, &[list_id, list_id], &cookie).await;
+
+    let (n_lists,): (i64,) = sqlx::query_as("SELECT count(*) FROM newsletter_issue_lists")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(n_lists, 1);
+}
@@ -1,7 +1,22 @@
 mod admin_dashboard;
+mod admin_email;
+mod admin_subscribers;
+mod admin_subscribers_csv;
+mod admin_users;
 mod change_password;
+mod consent;
 mod health_check;
 mod helpers;
 mod login;
+mod mailing_lists;
+mod newsletter_drafts;
 mod newsletters;
+mod password_reset;
+mod preferences;
+mod scheduled_newsletters;
+mod subscriber_data;
+mod subscription_protection;
 mod subscriptions;
+mod two_factor;
+mod unsubscribe;
+mod webhooks;
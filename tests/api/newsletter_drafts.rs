@@ -0,0 +1,191 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_response_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn save_draft(test_app: &TestApp, cookie: &str) -> Uuid {
+    let draft_request_body = serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "content": "<p>Draft body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": "",
+    });
+    let response = test_app.post_save_draft(&draft_request_body, cookie).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers()["location"].to_str().unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .expect("Expected a redirect to the draft page.")
+        .parse()
+        .unwrap()
+}
+
+async fn fetch_issues(test_app: &TestApp) -> Vec<(Uuid, String)> {
+    sqlx::query_as("SELECT newsletter_issue_id, status FROM newsletter_issues")
+        .fetch_all(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch newsletter issues.")
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let draft_id = save_draft(&test_app, &cookie).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        fetch_issues(&test_app).await,
+        vec![(draft_id, "draft".into())]
+    );
+    let html_page = test_app
+        .get_html_with_cookie(&format!("/admin/newsletters/drafts/{}", draft_id), &cookie)
+        .await;
+    assert!(html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn saving_a_draft_again_updates_it() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let draft_id = save_draft(&test_app, &cookie).await;
+
+    let response = test_app
+        .post_save_draft(
+            &serde_json::json!({
+                "draft_id": draft_id,
+                "title": "Updated title",
+                "text_content": "Updated body",
+                "content": "<p>Updated body</p>",
+            }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    let (title,): (String,) = sqlx::query_as("SELECT title FROM newsletter_issues")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(title, "Updated title");
+}
+
+#[tokio::test]
+async fn draft_preview_renders_both_versions() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let draft_id = save_draft(&test_app, &cookie).await;
+
+    let html_page = test_app
+        .get_html_with_cookie(
+            &format!("/admin/newsletters/drafts/{}/preview", draft_id),
+            &cookie,
+        )
+        .await;
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Draft body as HTML&lt;&#x2F;p&gt;""#));
+    assert!(html_page.contains("Draft body as plain text"));
+}
+
+#[tokio::test]
+async fn test_emails_are_only_sent_to_the_logged_in_admin() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let draft_id = save_draft(&test_app, &cookie).await;
+    let response = test_app.post_send_test_email(draft_id, &cookie).await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], test_app.test_user.email);
+    assert_eq!(body["Subject"], "[TEST] Draft title");
+    test_app.dispatch_all_pending_emails().await;
+    assert_eq!(
+        fetch_issues(&test_app).await,
+        vec![(draft_id, "draft".into())]
+    );
+}
+
+#[tokio::test]
+async fn test_emails_are_not_sent_without_an_admin_email_address() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    sqlx::query("UPDATE users SET email = NULL")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let draft_id = save_draft(&test_app, &cookie).await;
+    let response = test_app.post_send_test_email(draft_id, &cookie).await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/drafts/{}", draft_id));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_to_confirmed_subscribers() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let draft_id = save_draft(&test_app, &cookie).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        fetch_issues(&test_app).await,
+        vec![(draft_id, "published".into())]
+    );
+}
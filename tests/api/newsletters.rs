@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
@@ -7,7 +8,7 @@ use wiremock::{
 
 use crate::helpers::{
     assert_response_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
-    spawn_app,
+    spawn_app, TestApp,
 };
 
 #[tokio::test]
@@ -28,7 +29,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
-        "html_content": "<p>Newsletter body as HTML</p>",
+        "content": "<p>Newsletter body as HTML</p>",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = test_app
@@ -54,7 +55,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
-        "html_content": "<p>Newsletter body as HTML</p>",
+        "content": "<p>Newsletter body as HTML</p>",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = test_app
@@ -77,7 +78,7 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
-        "html_content": "<p>Newsletter body as HTML</p>",
+        "content": "<p>Newsletter body as HTML</p>",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = test_app
@@ -105,7 +106,7 @@ async fn concurrent_from_submission_is_handled_gracefully() {
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
-        "html_content": "<p>Newsletter body as HTML</p>",
+        "content": "<p>Newsletter body as HTML</p>",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response1 = test_app.post_newsletter_with_cookie(&newsletter_request_body, &cookie);
@@ -114,3 +115,347 @@ async fn concurrent_from_submission_is_handled_gracefully() {
     assert_eq!(response1.status(), response2.status());
     test_app.dispatch_all_pending_emails().await;
 }
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried_with_backoff() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let (n_retries, execute_after): (i16, DateTime<Utc>) =
+        sqlx::query_as("SELECT n_retries, execute_after FROM issue_delivery_queue")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch queued delivery.");
+    assert_eq!(n_retries, 1);
+    assert!(execute_after > Utc::now());
+}
+
+#[tokio::test]
+async fn deliveries_that_exhaust_their_retries_are_moved_to_failures() {
+    let mut test_app = spawn_app().await;
+    test_app.configuration.issue_delivery.max_retries = 0;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let (n_queued,): (i64,) = sqlx::query_as("SELECT count(*) FROM issue_delivery_queue")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+    let (subscriber_email,): (String,) =
+        sqlx::query_as("SELECT subscriber_email FROM issue_delivery_failures")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch delivery failure.");
+
+    let html_page = test_app.get_delivery_failures_html(&cookie).await;
+    assert!(html_page.contains(&subscriber_email));
+
+    let (issue_id, outcome): (uuid::Uuid, String) =
+        sqlx::query_as("SELECT newsletter_issue_id, outcome FROM issue_delivery_outcomes")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch delivery outcome.");
+    assert_eq!(outcome, "failed");
+    let html_page = test_app.get_newsletter_issue_html(issue_id, &cookie).await;
+    assert!(html_page.contains(&subscriber_email));
+}
+
+#[tokio::test]
+async fn delivered_issues_record_their_outcome_and_progress() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "ErrorCode": 0,
+            "Message": "OK",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+        })))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+
+    let (issue_id,): (uuid::Uuid,) =
+        sqlx::query_as("SELECT newsletter_issue_id FROM newsletter_issues")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    let html_page = test_app.get_newsletter_issue_html(issue_id, &cookie).await;
+    assert!(html_page.contains("Progress: 0%"));
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let (outcome, provider_message_id): (String, Option<String>) =
+        sqlx::query_as("SELECT outcome, provider_message_id FROM issue_delivery_outcomes")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch delivery outcome.");
+    assert_eq!(outcome, "sent");
+    assert_eq!(
+        provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+    let html_page = test_app.get_newsletter_issue_html(issue_id, &cookie).await;
+    assert!(html_page.contains("Progress: 100%"));
+}
+
+async fn publish_newsletter(test_app: &TestApp, cookie: &str) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_in_a_single_batch_request() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "first-id"},
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "second-id"},
+        ])))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    publish_newsletter(&test_app, &cookie).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let outcomes: Vec<(String,)> = sqlx::query_as("SELECT outcome FROM issue_delivery_outcomes")
+        .fetch_all(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(outcomes, vec![("sent".into(),), ("sent".into(),)]);
+}
+
+#[tokio::test]
+async fn rejected_messages_in_a_batch_are_retried_individually() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "first-id"},
+            {"ErrorCode": 406, "Message": "Inactive recipient"},
+        ])))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    publish_newsletter(&test_app, &cookie).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let outcomes: Vec<(String,)> = sqlx::query_as("SELECT outcome FROM issue_delivery_outcomes")
+        .fetch_all(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(outcomes, vec![("sent".into(),)]);
+    let retries: Vec<(i16,)> = sqlx::query_as("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(retries, vec![(1,)]);
+}
+
+#[tokio::test]
+async fn issues_are_personalised_for_each_recipient() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let (name,): (String,) = sqlx::query_as("SELECT name FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ name }}",
+        "text_content": "Hi {{ name }}, leave at {{ unsubscribe_url }}",
+        "content": "<p>Subscribed since {{ subscribed_at }}</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], format!("News for {}", name));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with(&format!("Hi {}, leave at http://", name)));
+    assert!(text_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert!(!body["HtmlBody"].as_str().unwrap().contains("{{"));
+}
+
+#[tokio::test]
+async fn issues_with_broken_placeholders_are_rejected_before_enqueuing() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    for html_content in ["<p>Hi {{ nmae }}</p>", "<p>Hi {{ name </p>"] {
+        let newsletter_request_body = serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "content": html_content,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        });
+        let response = test_app
+            .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+            .await;
+        assert_response_redirect_to(response, "/admin/newsletters");
+    }
+
+    let (issues,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM newsletter_issues")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(issues, 0);
+    let (tasks,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(tasks, 0);
+}
+
+#[tokio::test]
+async fn markdown_issues_are_sanitized_and_get_a_derived_plain_text_body() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_format": "markdown",
+        "content": "# Big news\n\nWe *shipped* it.\n\n<script>alert(1)</script>",
+        "text_content": "",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>Big news</h1>"));
+    assert!(html_body.contains("<em>shipped</em>"));
+    assert!(!html_body.contains("script"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Big news"));
+    assert!(text_body.contains("shipped"));
+    assert!(!text_body.contains('<'));
+
+    let (content_format, source_content, source_text_content): (String, String, Option<String>) =
+        sqlx::query_as(
+            "SELECT content_format, source_content, source_text_content FROM newsletter_issues",
+        )
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(content_format, "markdown");
+    assert!(source_content.starts_with("# Big news"));
+    assert_eq!(source_text_content, None);
+}
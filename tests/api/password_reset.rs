@@ -0,0 +1,221 @@
+use axum::{
+    body::Body,
+    http::{self, Request},
+};
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_response_redirect_to, path_and_query, spawn_app, TestApp};
+
+const NEW_PASSWORD: &str = "a wizard of earthsea";
+
+async fn post_forgot_password(test_app: &TestApp, email: &str) -> http::Response<Body> {
+    test_app
+        .post_form_with_cookie("/login/forgot", &serde_json::json!({"email": email}), "")
+        .await
+}
+
+/// Asks for a reset link for the test user and returns it.
+async fn request_reset_link(test_app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let response = post_forgot_password(test_app, &test_app.test_user.email).await;
+    assert_response_redirect_to(response, "/login");
+    let email_request = &wait_for_emails(test_app, 1).await[0];
+    path_and_query(test_app.get_confirmation_links(email_request).plain_text)
+}
+
+/// The emails are sent after the response, so wait a little for them.
+async fn wait_for_emails(test_app: &TestApp, n_emails: usize) -> Vec<wiremock::Request> {
+    for _ in 0..50 {
+        let requests = test_app.email_server.received_requests().await.unwrap();
+        if requests.len() >= n_emails {
+            return requests;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("Expected {} emails to be sent.", n_emails);
+}
+
+async fn post_reset(test_app: &TestApp, reset_path: &str) -> http::Response<Body> {
+    test_app
+        .post_form_with_cookie(
+            reset_path,
+            &serde_json::json!({
+                "new_password": NEW_PASSWORD,
+                "new_password_check": NEW_PASSWORD,
+            }),
+            "",
+        )
+        .await
+}
+
+async fn get_status(test_app: &TestApp, uri: &str) -> u16 {
+    test_app
+        .app()
+        .await
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_the_user_choose_a_new_password() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+
+    assert_eq!(get_status(&test_app, &reset_path).await, 200);
+    let response = post_reset(&test_app, &reset_path).await;
+    assert_response_redirect_to(response, "/login");
+
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": test_app.test_user.username,
+            "password": test_app.test_user.password,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/login");
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": test_app.test_user.username,
+            "password": NEW_PASSWORD,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_user_is_told_their_password_was_reset() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+
+    post_reset(&test_app, &reset_path).await;
+
+    let email_requests = wait_for_emails(&test_app, 2).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[1].body).unwrap();
+    assert_eq!(body["To"], test_app.test_user.email);
+    assert_eq!(body["Subject"], "Your password was changed");
+}
+
+#[tokio::test]
+async fn weak_passwords_are_rejected_without_using_up_the_link() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &reset_path,
+            &serde_json::json!({
+                "new_password": "password123",
+                "new_password_check": "password123",
+            }),
+            "",
+        )
+        .await;
+    assert_response_redirect_to(response, &reset_path);
+
+    let response = post_reset(&test_app, &reset_path).await;
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_every_session() {
+    let test_app = spawn_app().await;
+    let first_cookie = test_app.login_and_get_cookie().await;
+    let second_cookie = test_app.login_and_get_cookie().await;
+    let reset_path = request_reset_link(&test_app).await;
+
+    post_reset(&test_app, &reset_path).await;
+
+    for cookie in [first_cookie, second_cookie] {
+        let response = test_app.get_admin_dashboard_with_cookie(&cookie).await;
+        assert_response_redirect_to(response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+    post_reset(&test_app, &reset_path).await;
+
+    let response = post_reset(&test_app, &reset_path).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_status(&test_app, &reset_path).await, 401);
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+    sqlx::query("UPDATE password_reset_tokens SET created_at = now() - interval '31 minutes'")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    let response = post_reset(&test_app, &reset_path).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn reset_tokens_are_stored_hashed() {
+    let test_app = spawn_app().await;
+    let reset_path = request_reset_link(&test_app).await;
+    let token = reset_path.split("token=").nth(1).unwrap();
+
+    let (token_hash,): (String,) = sqlx::query_as("SELECT token_hash FROM password_reset_tokens")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    assert_ne!(token_hash, token);
+    assert!(!token_hash.contains(token));
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_and_no_email() {
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Reset requests are counted in Redis, which outlasts the test database.
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let response = post_forgot_password(&test_app, &email).await;
+
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn reset_requests_for_an_address_are_rate_limited() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.app_state.login.max_password_resets_per_email;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(u64::from(max_requests))
+        .mount(&test_app.email_server)
+        .await;
+
+    for _ in 0..max_requests {
+        let response = post_forgot_password(&test_app, &test_app.test_user.email).await;
+        assert_response_redirect_to(response, "/login");
+    }
+    let response = post_forgot_password(&test_app, &test_app.test_user.email.to_uppercase()).await;
+
+    assert_response_redirect_to(response, "/login/forgot");
+    wait_for_emails(&test_app, max_requests as usize).await;
+}
@@ -0,0 +1,329 @@
+use axum::{body::Body, http::Request};
+use http_body_util::BodyExt;
+use reqwest::Url;
+use sqlx::prelude::FromRow;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::controller::preferences_link;
+
+use crate::{
+    helpers::{
+        assert_response_redirect_to, create_confirmed_subscriber, path_and_query, spawn_app,
+        TestApp,
+    },
+    subscriptions::get_confirmation,
+};
+
+#[derive(FromRow)]
+struct Subscription {
+    id: Uuid,
+    email: String,
+    name: String,
+    delivery_format: String,
+}
+
+async fn fetch_subscription(test_app: &TestApp) -> Subscription {
+    sqlx::query_as("SELECT id, email, name, delivery_format FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch saved subscription.")
+}
+
+/// The path and query of the preferences page, or of one of its forms.
+pub fn preferences_path(test_app: &TestApp, subscriber_id: Uuid, path: &str) -> String {
+    let mut link = Url::parse(&preferences_link(
+        &test_app.app_state.base_url,
+        &test_app.app_state.hmac_secret,
+        subscriber_id,
+    ))
+    .unwrap();
+    link.set_path(path);
+    path_and_query(link)
+}
+
+async fn get_preferences(test_app: &TestApp, path_and_query: &str) -> (u16, String) {
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri(path_and_query)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_subscriber_details() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+
+    let (status, html) = get_preferences(
+        &test_app,
+        &preferences_path(&test_app, subscription.id, "/subscriptions/preferences"),
+    )
+    .await;
+
+    assert_eq!(status, 200);
+    assert!(html.contains(&subscription.email));
+    assert!(html.contains(&subscription.name));
+}
+
+#[tokio::test]
+async fn the_preferences_page_rejects_a_forged_token() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+
+    let (status, _) = get_preferences(
+        &test_app,
+        &format!(
+            "/subscriptions/preferences?subscriber_id={}&token={}",
+            subscription.id,
+            "a".repeat(64)
+        ),
+    )
+    .await;
+
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_links_do_not_open_the_preferences_page() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+    let unsubscribe_link = zero2prod::controller::unsubscribe_link(
+        &test_app.app_state.base_url,
+        &test_app.app_state.hmac_secret,
+        subscription.id,
+    );
+    let mut link = Url::parse(&unsubscribe_link).unwrap();
+    link.set_path("/subscriptions/preferences");
+
+    let (status, _) = get_preferences(&test_app, &path_and_query(link)).await;
+
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_name_and_delivery_format() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(&test_app, subscription.id, "/subscriptions/preferences"),
+            &serde_json::json!({"name": "Ursula Le Guin", "delivery_format": "text"}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscription = fetch_subscription(&test_app).await;
+    assert_eq!(subscription.name, "Ursula Le Guin");
+    assert_eq!(subscription.delivery_format, "text");
+}
+
+#[tokio::test]
+async fn invalid_names_are_rejected() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(&test_app, subscription.id, "/subscriptions/preferences"),
+            &serde_json::json!({"name": "  ", "delivery_format": "html"}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(fetch_subscription(&test_app).await.name, subscription.name);
+}
+
+#[tokio::test]
+async fn a_new_email_takes_effect_once_confirmed() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(
+                &test_app,
+                subscription.id,
+                "/subscriptions/preferences/email",
+            ),
+            &serde_json::json!({"email": "ursula_le_guin@gmail.com"}),
+            "",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        fetch_subscription(&test_app).await.email,
+        subscription.email
+    );
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    let links = test_app.get_confirmation_links(&email_request);
+    let (status, html) =
+        get_confirmation(test_app.app().await, path_and_query(links.plain_text)).await;
+
+    assert_eq!(status, 200);
+    assert!(html.contains("email address has been updated"));
+    assert_eq!(
+        fetch_subscription(&test_app).await.email,
+        "ursula_le_guin@gmail.com"
+    );
+}
+
+#[tokio::test]
+async fn an_email_already_in_use_is_not_sent_a_confirmation() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+    sqlx::query(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+            VALUES ($1, 'taken@gmail.com', 'Taken', now(), 'confirmed')",
+    )
+    .bind(Uuid::new_v4())
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(
+                &test_app,
+                subscription.id,
+                "/subscriptions/preferences/email",
+            ),
+            &serde_json::json!({"email": "taken@gmail.com"}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn email_changes_are_rate_limited_per_address() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+    let max_signups = test_app
+        .app_state
+        .subscriptions
+        .max_signups_per_email_per_hour;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(u64::from(max_signups))
+        .mount(&test_app.email_server)
+        .await;
+    let change_email_path = preferences_path(
+        &test_app,
+        subscription.id,
+        "/subscriptions/preferences/email",
+    );
+
+    for _ in 0..max_signups {
+        let response = test_app
+            .post_form_with_cookie(
+                &change_email_path,
+                &serde_json::json!({"email": "victim@gmail.com"}),
+                "",
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = test_app
+        .post_form_with_cookie(
+            &change_email_path,
+            &serde_json::json!({"email": "victim@gmail.com"}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn text_only_subscribers_receive_issues_without_an_html_body() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+    test_app
+        .post_form_with_cookie(
+            &preferences_path(&test_app, subscription.id, "/subscriptions/preferences"),
+            &serde_json::json!({"name": subscription.name, "delivery_format": "text"}),
+            "",
+        )
+        .await;
+    let cookie = test_app.login_and_get_cookie().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletter_with_cookie(
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body.get("HtmlBody").is_none());
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Newsletter body as HTML"));
+}
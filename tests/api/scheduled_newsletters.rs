@@ -0,0 +1,179 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_response_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+fn send_at_in(delay: Duration) -> String {
+    (Utc::now() + delay).format("%Y-%m-%dT%H:%M").to_string()
+}
+
+async fn schedule_newsletter(test_app: &TestApp, cookie: &str) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": send_at_in(Duration::hours(1)),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    let (issue_id,): (Uuid,) = sqlx::query_as("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch scheduled issue.");
+    issue_id
+}
+
+async fn make_due(test_app: &TestApp, issue_id: Uuid) {
+    sqlx::query(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 second' \
+            WHERE newsletter_issue_id = $1",
+    )
+    .bind(issue_id)
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .expect("Failed to update scheduled issue.");
+}
+
+async fn fetch_status(test_app: &TestApp, issue_id: Uuid) -> String {
+    let (status,): (String,) =
+        sqlx::query_as("SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1")
+            .bind(issue_id)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch issue status.");
+    status
+}
+
+async fn count_pending_deliveries(test_app: &TestApp) -> i64 {
+    let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM issue_delivery_queue")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to count pending deliveries.");
+    count
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_only_delivered_once_due() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&test_app, &cookie).await;
+    assert_eq!(fetch_status(&test_app, issue_id).await, "scheduled");
+
+    assert_eq!(test_app.promote_scheduled_issues().await, 0);
+    assert_eq!(count_pending_deliveries(&test_app).await, 0);
+
+    make_due(&test_app, issue_id).await;
+    assert_eq!(test_app.promote_scheduled_issues().await, 1);
+    assert_eq!(fetch_status(&test_app, issue_id).await, "published");
+    assert_eq!(count_pending_deliveries(&test_app).await, 1);
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_delivered() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&test_app, &cookie).await;
+    let response = test_app
+        .post_cancel_newsletter_issue(issue_id, &cookie)
+        .await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/{}", issue_id));
+    assert_eq!(fetch_status(&test_app, issue_id).await, "cancelled");
+
+    make_due(&test_app, issue_id).await;
+    assert_eq!(test_app.promote_scheduled_issues().await, 0);
+    assert_eq!(count_pending_deliveries(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let issue_id = schedule_newsletter(&test_app, &cookie).await;
+
+    let send_at = send_at_in(Duration::days(2));
+    let response = test_app
+        .post_reschedule_newsletter_issue(
+            issue_id,
+            &serde_json::json!({ "send_at": send_at }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/{}", issue_id));
+
+    let (scheduled_for,): (chrono::DateTime<Utc>,) = sqlx::query_as(
+        "SELECT scheduled_for FROM newsletter_issues WHERE newsletter_issue_id = $1",
+    )
+    .bind(issue_id)
+    .fetch_one(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+    assert_eq!(scheduled_for.format("%Y-%m-%dT%H:%M").to_string(), send_at);
+}
+
+#[tokio::test]
+async fn published_issues_cannot_be_cancelled() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let issue_id = schedule_newsletter(&test_app, &cookie).await;
+    make_due(&test_app, issue_id).await;
+    test_app.promote_scheduled_issues().await;
+
+    let response = test_app
+        .post_cancel_newsletter_issue(issue_id, &cookie)
+        .await;
+    assert_response_redirect_to(response, &format!("/admin/newsletters/{}", issue_id));
+    assert_eq!(fetch_status(&test_app, issue_id).await, "published");
+}
+
+#[tokio::test]
+async fn send_at_times_in_the_past_are_rejected() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": send_at_in(-Duration::hours(1)),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+
+    let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM newsletter_issues")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
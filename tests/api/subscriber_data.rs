@@ -0,0 +1,360 @@
+use axum::{body::Body, http::Request};
+use chrono::{DateTime, Duration, Utc};
+use http_body_util::BodyExt;
+use reqwest::Url;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::controller::data_links;
+
+use crate::{
+    helpers::{create_confirmed_subscriber, path_and_query, spawn_app, TestApp},
+    preferences::preferences_path,
+};
+
+async fn subscriber_id(test_app: &TestApp) -> Uuid {
+    subscriber(test_app).await.0
+}
+
+async fn subscriber(test_app: &TestApp) -> (Uuid, String) {
+    sqlx::query_as("SELECT id, email FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap()
+}
+
+async fn count(test_app: &TestApp, query: &str) -> i64 {
+    let (n,): (i64,) = sqlx::query_as(query)
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    n
+}
+
+async fn get_export(test_app: &TestApp, path_and_query: &str) -> (u16, String) {
+    let response = test_app
+        .app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri(path_and_query)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// The export and erase paths signed for `subscriber_id` at `issued_at`.
+fn data_paths(
+    test_app: &TestApp,
+    subscriber_id: Uuid,
+    issued_at: DateTime<Utc>,
+) -> (String, String) {
+    let (export_link, erase_link) = data_links(
+        &test_app.app_state.base_url,
+        &test_app.app_state.hmac_secret,
+        subscriber_id,
+        issued_at,
+    );
+    (
+        path_and_query(Url::parse(&export_link).unwrap()),
+        path_and_query(Url::parse(&erase_link).unwrap()),
+    )
+}
+
+/// Publishes an issue to the confirmed subscriber and delivers it.
+async fn deliver_an_issue(test_app: &TestApp) {
+    let cookie = test_app.login_and_get_cookie().await;
+    test_app
+        .post_newsletter_with_cookie(
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let (subscriber_id, email) = subscriber(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    deliver_an_issue(&test_app).await;
+
+    let (export_path, _) = data_paths(&test_app, subscriber_id, Utc::now());
+    let (status, body) = get_export(&test_app, &export_path).await;
+
+    assert_eq!(status, 200);
+    let data: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(data["subscription"]["email"], email);
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["lists"].as_array().unwrap().len(), 1);
+    assert_eq!(data["consent_events"].as_array().unwrap().len(), 2);
+    assert_eq!(data["delivery_outcomes"][0]["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn the_data_export_rejects_a_forged_token() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscriber_id = subscriber_id(&test_app).await;
+
+    let (status, _) = get_export(
+        &test_app,
+        &format!(
+            "/subscriptions/data?subscriber_id={}&issued_at={}&token={}",
+            subscriber_id,
+            Utc::now().timestamp(),
+            "a".repeat(64)
+        ),
+    )
+    .await;
+
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn subscribers_are_emailed_their_data_links() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let (subscriber_id, email) = subscriber(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(&test_app, subscriber_id, "/subscriptions/data"),
+            &serde_json::json!({}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], email);
+    let links = linkify::LinkFinder::new()
+        .links(body["TextBody"].as_str().unwrap())
+        .map(|l| Url::parse(l.as_str()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(links.len(), 2);
+    let (status, body) = get_export(&test_app, &path_and_query(links[0].clone())).await;
+    assert_eq!(status, 200);
+    assert!(body.contains(&email));
+    let (status, body) = get_export(&test_app, &path_and_query(links[1].clone())).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Erase my data"));
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM subscriptions").await,
+        1
+    );
+}
+
+#[tokio::test]
+async fn the_preferences_link_cannot_export_or_erase_data() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscriber_id = subscriber_id(&test_app).await;
+
+    let (status, _) = get_export(
+        &test_app,
+        &preferences_path(&test_app, subscriber_id, "/subscriptions/data"),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let response = test_app
+        .post_form_with_cookie(
+            &preferences_path(&test_app, subscriber_id, "/subscriptions/data/erase"),
+            &serde_json::json!({}),
+            "",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM subscriptions").await,
+        1
+    );
+}
+
+#[tokio::test]
+async fn data_links_expire() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscriber_id = subscriber_id(&test_app).await;
+    let ttl = test_app.app_state.subscriptions.data_link_ttl();
+    let (export_path, erase_path) = data_paths(
+        &test_app,
+        subscriber_id,
+        Utc::now() - ttl - Duration::minutes(1),
+    );
+
+    let (status, _) = get_export(&test_app, &export_path).await;
+    assert_eq!(status, 401);
+
+    let response = test_app
+        .post_form_with_cookie(&erase_path, &serde_json::json!({}), "")
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM subscriptions").await,
+        1
+    );
+}
+
+#[tokio::test]
+async fn an_export_link_cannot_erase_data() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscriber_id = subscriber_id(&test_app).await;
+    let (export_path, _) = data_paths(&test_app, subscriber_id, Utc::now());
+    let erase_path = export_path.replace("/subscriptions/data?", "/subscriptions/data/erase?");
+
+    let response = test_app
+        .post_form_with_cookie(&erase_path, &serde_json::json!({}), "")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erasure_removes_the_subscriber_and_anonymizes_delivery_history() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let (subscriber_id, email) = subscriber(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    deliver_an_issue(&test_app).await;
+    sqlx::query(
+        "INSERT INTO email_events \
+            (email_event_id, provider, record_type, email, payload, received_at) \
+            VALUES ($1, 'postmark', 'Delivery', $2, '{}', now())",
+    )
+    .bind(Uuid::new_v4())
+    .bind(&email)
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+
+    let response = test_app
+        .post_form_with_cookie(
+            &data_paths(&test_app, subscriber_id, Utc::now()).1,
+            &serde_json::json!({}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM subscriptions").await,
+        0
+    );
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM subscription_tokens").await,
+        0
+    );
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM list_memberships").await,
+        0
+    );
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM email_events").await,
+        0
+    );
+    let (n_outcomes,): (i64,) =
+        sqlx::query_as("SELECT count(*) FROM issue_delivery_outcomes WHERE subscriber_email = $1")
+            .bind(&email)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(n_outcomes, 0);
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM issue_delivery_outcomes").await,
+        1
+    );
+}
+
+#[tokio::test]
+async fn erasure_drops_pending_deliveries() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(test_app.app().await, &test_app).await;
+    let subscriber_id = subscriber_id(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let cookie = test_app.login_and_get_cookie().await;
+    test_app
+        .post_newsletter_with_cookie(
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM issue_delivery_queue").await,
+        1
+    );
+
+    test_app
+        .post_form_with_cookie(
+            &data_paths(&test_app, subscriber_id, Utc::now()).1,
+            &serde_json::json!({}),
+            "",
+        )
+        .await;
+
+    assert_eq!(
+        count(&test_app, "SELECT count(*) FROM issue_delivery_queue").await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_subscriber_is_not_found() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_form_with_cookie(
+            &data_paths(&test_app, Uuid::new_v4(), Utc::now()).1,
+            &serde_json::json!({}),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,276 @@
+use axum::{
+    body::Body,
+    http::{self, Request},
+};
+use http_body_util::BodyExt;
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::startup::app;
+
+use crate::{
+    consent::post_subscriptions_from,
+    helpers::{spawn_app, TestApp},
+    subscriptions::post_subscriptions,
+};
+
+async fn count_subscriptions(test_app: &TestApp) -> i64 {
+    let (n,): (i64,) = sqlx::query_as("SELECT count(*) FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    n
+}
+
+async fn mount_email_server(test_app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+}
+
+fn leading_zero_bits(challenge: &str, email: &str, nonce: &str) -> u32 {
+    let hash = Sha256::digest(format!("{}:{}:{}", challenge, email, nonce));
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Finds a nonce whose hash for `email` has a number of leading zero bits
+/// `accept`s.
+fn find_nonce(challenge: &str, email: &str, accept: impl Fn(u32) -> bool) -> String {
+    (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| accept(leading_zero_bits(challenge, email, nonce)))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signups_filling_in_the_honeypot_are_silently_ignored() {
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = post_subscriptions(
+        test_app.app().await,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example.com",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn an_email_address_can_only_be_signed_up_a_few_times_an_hour() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let max_signups = test_app
+        .app_state
+        .subscriptions
+        .max_signups_per_email_per_hour;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    for _ in 0..max_signups {
+        let response = post_subscriptions(test_app.app().await, body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    // Changing the case doesn't get around the limit.
+    let response = post_subscriptions(
+        test_app.app().await,
+        "name=le%20guin&email=Ursula_Le_Guin%40gmail.com",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let n_emails = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_emails, max_signups as usize);
+}
+
+#[tokio::test]
+async fn a_client_can_only_sign_up_a_few_addresses_an_hour() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let max_signups = test_app.app_state.subscriptions.max_signups_per_ip_per_hour;
+
+    for i in 0..max_signups {
+        let response = post_subscriptions_from(
+            test_app.app().await,
+            &format!("name=le%20guin&email=ursula{}%40gmail.com", i),
+            "203.0.113.7:51234",
+            &[],
+        )
+        .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=one_too_many%40gmail.com",
+        "203.0.113.7:51234",
+        &[],
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // Other clients are not affected.
+    let response = post_subscriptions_from(
+        test_app.app().await,
+        "name=le%20guin&email=one_too_many%40gmail.com",
+        "198.51.100.23:40000",
+        &[],
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_proof_of_work_is_required_when_enabled() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let mut app_state = test_app.app_state.clone();
+    app_state.subscriptions.proof_of_work_difficulty = 8;
+    let router = test_app.register_layer(app(app_state)).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = post_subscriptions(router.clone(), body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/subscriptions/challenge")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let challenge: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(challenge["difficulty"], 8);
+    let challenge = challenge["challenge"].as_str().unwrap();
+
+    let email = "ursula_le_guin@gmail.com";
+    let wrong_nonce = find_nonce(challenge, email, |bits| bits == 0);
+    let response = post_subscriptions(
+        router.clone(),
+        &format!(
+            "{}&pow_challenge={}&pow_nonce={}",
+            body, challenge, wrong_nonce
+        ),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let nonce = find_nonce(challenge, email, |bits| bits >= 8);
+    let response = post_subscriptions(
+        router,
+        &format!("{}&pow_challenge={}&pow_nonce={}", body, challenge, nonce),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn a_solved_challenge_is_good_for_one_signup_of_one_address() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let mut app_state = test_app.app_state.clone();
+    app_state.subscriptions.proof_of_work_difficulty = 8;
+    let router = test_app.register_layer(app(app_state)).await;
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/subscriptions/challenge")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let challenge: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    let challenge = challenge["challenge"].as_str().unwrap();
+    // A nonce that happens to solve the challenge for the other address too
+    // wouldn't show anything.
+    let nonce = (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| {
+            leading_zero_bits(challenge, "ursula_le_guin@gmail.com", nonce) >= 8
+                && leading_zero_bits(challenge, "octavia_butler@gmail.com", nonce) < 8
+        })
+        .unwrap();
+    let signup = |email: &str| {
+        format!(
+            "name=le%20guin&email={}&pow_challenge={}&pow_nonce={}",
+            email, challenge, nonce
+        )
+    };
+
+    let response = post_subscriptions(router.clone(), &signup("octavia_butler@gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post_subscriptions(router.clone(), &signup("ursula_le_guin@gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = post_subscriptions(router, &signup("ursula_le_guin@gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_subscriptions(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn a_rejected_form_does_not_use_up_the_solved_challenge() {
+    let test_app = spawn_app().await;
+    mount_email_server(&test_app).await;
+    let mut app_state = test_app.app_state.clone();
+    app_state.subscriptions.proof_of_work_difficulty = 8;
+    let router = test_app.register_layer(app(app_state)).await;
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/subscriptions/challenge")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let challenge: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    let challenge = challenge["challenge"].as_str().unwrap();
+    let nonce = (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| leading_zero_bits(challenge, "ursula_le_guin@gmail.com", nonce) >= 8)
+        .unwrap();
+    let signup = |name: &str| {
+        format!(
+            "name={}&email=ursula_le_guin@gmail.com&pow_challenge={}&pow_nonce={}",
+            name, challenge, nonce
+        )
+    };
+
+    let response = post_subscriptions(router.clone(), &signup("")).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post_subscriptions(router, &signup("le%20guin")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&test_app).await, 1);
+}
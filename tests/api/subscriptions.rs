@@ -3,14 +3,15 @@ use axum::{
     http::{self, Request},
     Router,
 };
+use http_body_util::BodyExt;
 use tower::ServiceExt;
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
 };
-use zero2prod::startup::app;
+use zero2prod::{startup::app, token_cleanup_worker::purge_expired_tokens};
 
-use crate::helpers::{path_and_query, spawn_app};
+use crate::helpers::{create_unconfirmed_subscriber, path_and_query, spawn_app};
 
 #[derive(sqlx::FromRow, Debug, PartialEq, Eq)]
 struct Subscription {
@@ -35,6 +36,22 @@ pub async fn post_subscriptions(app: Router, body: &str) -> http::Response<Body>
     .unwrap()
 }
 
+pub async fn get_confirmation(app: Router, path_and_query: String) -> (u16, String) {
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(path_and_query)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
     let test_app = spawn_app().await;
@@ -196,7 +213,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .expect(1)
+        .expect(2)
         .mount(&test_app.email_server)
         .await;
     post_subscriptions(app.clone(), body).await;
@@ -229,7 +246,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .expect(1)
+        .expect(2)
         .mount(&test_app.email_server)
         .await;
     post_subscriptions(app.clone(), body).await;
@@ -255,3 +272,228 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "fan-tastic.z");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_confirmation_email() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let body = "name=fan-tastic.z&email=fantastic.fun.zf@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    let first = post_subscriptions(app.clone(), body).await;
+    let second = post_subscriptions(app, body).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let first_links = test_app.get_confirmation_links(&email_requests[0]);
+    let second_links = test_app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+    let (subscriptions,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(subscriptions, 1);
+}
+
+#[tokio::test]
+async fn subscribing_a_confirmed_address_succeeds_without_sending_an_email() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let body = "name=fan-tastic.z&email=fantastic.fun.zf@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    post_subscriptions(app.clone(), body).await;
+    sqlx::query("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    let response = post_subscriptions(app, body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved: Subscription = sqlx::query_as("SELECT email, name, status FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_confirmation_link_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let confirmation_links = create_unconfirmed_subscriber(app.clone(), &test_app).await;
+    let path_and_query = path_and_query(confirmation_links.html);
+
+    let (status, body) = get_confirmation(app.clone(), path_and_query.clone()).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("your subscription is confirmed"));
+
+    let (status, body) = get_confirmation(app, path_and_query).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("already confirmed"));
+    let (consumed,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM subscription_tokens WHERE consumed_at IS NOT NULL")
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(consumed, 1);
+}
+
+#[tokio::test]
+async fn earlier_confirmation_links_are_invalidated_once_one_is_used() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let body = "name=fan-tastic.z&email=fantastic.fun.zf@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&test_app.email_server)
+        .await;
+    post_subscriptions(app.clone(), body).await;
+    post_subscriptions(app.clone(), body).await;
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let first_link = path_and_query(test_app.get_confirmation_links(&email_requests[0]).html);
+    let second_link = path_and_query(test_app.get_confirmation_links(&email_requests[1]).html);
+
+    let (status, _) = get_confirmation(app.clone(), second_link).await;
+    assert_eq!(status, 200);
+    let (status, body) = get_confirmation(app, first_link).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("already confirmed"));
+}
+
+#[tokio::test]
+async fn an_expired_confirmation_link_does_not_confirm_the_subscriber() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let confirmation_links = create_unconfirmed_subscriber(app.clone(), &test_app).await;
+    sqlx::query("UPDATE subscription_tokens SET created_at = now() - interval '73 hours'")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    let (status, body) = get_confirmation(app, path_and_query(confirmation_links.html)).await;
+
+    assert_eq!(status, 410);
+    assert!(body.contains("expired"));
+    let saved: Subscription = sqlx::query_as("SELECT email, name, status FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn an_unknown_confirmation_token_is_rejected_with_a_401() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+
+    let (status, body) = get_confirmation(
+        app,
+        "/subscriptions/confirm?subscription_token=not-a-real-token".to_string(),
+    )
+    .await;
+
+    assert_eq!(status, 401);
+    assert!(body.contains("recognise this confirmation link"));
+}
+
+#[tokio::test]
+async fn purging_deletes_tokens_past_their_retention_window_only() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    create_unconfirmed_subscriber(app.clone(), &test_app).await;
+    create_unconfirmed_subscriber(app, &test_app).await;
+    sqlx::query(
+        r#"
+        UPDATE subscription_tokens SET created_at = now() - interval '1000 hours'
+        WHERE subscription_token = (SELECT subscription_token FROM subscription_tokens LIMIT 1)
+        "#,
+    )
+    .execute(test_app.app_state.db_pool.as_ref())
+    .await
+    .unwrap();
+
+    let purged = purge_expired_tokens(
+        &test_app.app_state.db_pool,
+        &test_app.configuration.subscriptions,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(purged, 1);
+    let (remaining,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM subscription_tokens")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(remaining, 1);
+}
+
+#[tokio::test]
+async fn the_confirmation_email_is_personalised_with_separate_text_and_html_bodies() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state);
+    let body = "name=fan-tastic.z&email=fantastic.fun.zf@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    post_subscriptions(app, body).await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.contains("Hi fan-tastic.z,"));
+    assert!(text_body.contains("Hi fan-tastic.z,"));
+    assert!(html_body.contains("<a href="));
+    assert!(!text_body.contains('<'));
+}
+
+#[tokio::test]
+async fn confirming_a_subscription_sends_a_welcome_email() {
+    let test_app = spawn_app().await;
+    let app = app(test_app.app_state.clone());
+    let body = "name=fan-tastic.z&email=fantastic.fun.zf@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    post_subscriptions(app.clone(), body).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    get_confirmation(app, path_and_query(confirmation_links.html)).await;
+
+    let welcome_request = &test_app.email_server.received_requests().await.unwrap()[1];
+    let welcome: serde_json::Value = serde_json::from_slice(&welcome_request.body).unwrap();
+    assert_eq!(welcome["To"], "fantastic.fun.zf@gmail.com");
+    assert_eq!(welcome["Subject"], "Your subscription is confirmed");
+    assert!(welcome["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi fan-tastic.z,"));
+    assert!(welcome["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?subscriber_id="));
+}
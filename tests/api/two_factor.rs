@@ -0,0 +1,244 @@
+use axum::{
+    body::Body,
+    http::{self, header},
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use zero2prod::{authentication::enable_two_factor, domain::TotpSecret};
+
+use crate::helpers::{assert_response_redirect_to, get_cookie, spawn_app, TestApp};
+
+/// Turns on two-factor authentication for the test user, as if they had
+/// enrolled a minute ago, and returns their secret and recovery codes.
+async fn enroll(test_app: &TestApp) -> (TotpSecret, Vec<String>) {
+    let secret = TotpSecret::generate();
+    let step = TotpSecret::step(Utc::now() - Duration::minutes(1));
+    let recovery_codes = enable_two_factor(
+        test_app.test_user.user_id,
+        &secret,
+        step,
+        &test_app.app_state.db_pool,
+    )
+    .await
+    .unwrap();
+    (secret, recovery_codes)
+}
+
+/// Gives the right password and returns the cookie of the half logged in
+/// session.
+async fn log_in_with_password(test_app: &TestApp) -> String {
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": test_app.test_user.username,
+            "password": test_app.test_user.password,
+        }))
+        .await;
+    assert_eq!(response.headers()[header::LOCATION], "/login/two-factor");
+    get_cookie(response)
+}
+
+async fn post_code(test_app: &TestApp, code: &str, cookie: &str) -> http::Response<Body> {
+    test_app
+        .post_form_with_cookie(
+            "/login/two-factor",
+            &serde_json::json!({"code": code}),
+            cookie,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn users_with_two_factor_need_a_code_to_log_in() {
+    let test_app = spawn_app().await;
+    let (secret, _) = enroll(&test_app).await;
+    let cookie = log_in_with_password(&test_app).await;
+
+    let response = test_app.get_admin_dashboard_with_cookie(&cookie).await;
+    assert_response_redirect_to(response, "/login");
+
+    let response = post_code(&test_app, "000000", &cookie).await;
+    assert_response_redirect_to(response, "/login/two-factor");
+
+    let response = post_code(&test_app, &secret.code_at(Utc::now()), &cookie).await;
+    let cookie = get_cookie(response);
+    let response = test_app.get_admin_dashboard_with_cookie(&cookie).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn authenticator_codes_cannot_be_replayed() {
+    let test_app = spawn_app().await;
+    let (secret, _) = enroll(&test_app).await;
+    let code = secret.code_at(Utc::now());
+    let cookie = log_in_with_password(&test_app).await;
+    let response = post_code(&test_app, &code, &cookie).await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+
+    let cookie = log_in_with_password(&test_app).await;
+    let response = post_code(&test_app, &code, &cookie).await;
+
+    assert_response_redirect_to(response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_can_each_be_used_once() {
+    let test_app = spawn_app().await;
+    let (_, recovery_codes) = enroll(&test_app).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let cookie = log_in_with_password(&test_app).await;
+    let response = post_code(&test_app, &recovery_codes[0].to_uppercase(), &cookie).await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+
+    let cookie = log_in_with_password(&test_app).await;
+    let response = post_code(&test_app, &recovery_codes[0], &cookie).await;
+    assert_response_redirect_to(response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_step_needs_the_password_first() {
+    let test_app = spawn_app().await;
+    let (secret, _) = enroll(&test_app).await;
+
+    let response = post_code(&test_app, &secret.code_at(Utc::now()), "").await;
+
+    assert_response_redirect_to(response, "/login");
+}
+
+#[tokio::test]
+async fn enrolling_needs_a_code_from_the_new_secret_and_shows_recovery_codes() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let html = test_app
+        .get_html_with_cookie("/admin/two-factor", &cookie)
+        .await;
+    let secret = html
+        .split("Secret: <code>")
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .unwrap();
+    assert!(html.contains("otpauth:"));
+    let secret = TotpSecret::parse_base32(secret).unwrap();
+
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/two-factor",
+            &serde_json::json!({
+                "code": "000000",
+                "current_password": test_app.test_user.password,
+            }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/two-factor");
+
+    let html = test_app
+        .get_html_with_cookie("/admin/two-factor", &cookie)
+        .await;
+    assert!(html.contains(&secret.base32()));
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/two-factor",
+            &serde_json::json!({
+                "code": secret.code_at(Utc::now()),
+                "current_password": test_app.test_user.password,
+            }),
+            &cookie,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let (n_codes,): (i64,) =
+        sqlx::query_as("SELECT count(*) FROM recovery_codes WHERE user_id = $1")
+            .bind(test_app.test_user.user_id)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(n_codes, 10);
+    log_in_with_password(&test_app).await;
+}
+
+#[tokio::test]
+async fn turning_two_factor_off_needs_a_code() {
+    let test_app = spawn_app().await;
+    let (_, recovery_codes) = enroll(&test_app).await;
+    let cookie = log_in_with_password(&test_app).await;
+    let cookie = get_cookie(post_code(&test_app, &recovery_codes[0], &cookie).await);
+
+    test_app
+        .post_form_with_cookie(
+            "/admin/two-factor/disable",
+            &serde_json::json!({
+                "code": "000000",
+                "current_password": test_app.test_user.password,
+            }),
+            &cookie,
+        )
+        .await;
+    log_in_with_password(&test_app).await;
+
+    test_app
+        .post_form_with_cookie(
+            "/admin/two-factor/disable",
+            &serde_json::json!({
+                "code": recovery_codes[1],
+                "current_password": test_app.test_user.password,
+            }),
+            &cookie,
+        )
+        .await;
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": test_app.test_user.username,
+            "password": test_app.test_user.password,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn turning_two_factor_on_or_off_needs_the_password() {
+    let test_app = spawn_app().await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let html = test_app
+        .get_html_with_cookie("/admin/two-factor", &cookie)
+        .await;
+    let secret = html
+        .split("Secret: <code>")
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .unwrap();
+    let secret = TotpSecret::parse_base32(secret).unwrap();
+
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/two-factor",
+            &serde_json::json!({
+                "code": secret.code_at(Utc::now()),
+                "current_password": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/two-factor");
+    let response = test_app
+        .post_login(serde_json::json!({
+            "username": test_app.test_user.username,
+            "password": test_app.test_user.password,
+        }))
+        .await;
+    assert_response_redirect_to(response, "/admin/dashboard");
+
+    let (_, recovery_codes) = enroll(&test_app).await;
+    let response = test_app
+        .post_form_with_cookie(
+            "/admin/two-factor/disable",
+            &serde_json::json!({
+                "code": recovery_codes[0],
+                "current_password": Uuid::new_v4().to_string(),
+            }),
+            &cookie,
+        )
+        .await;
+    assert_response_redirect_to(response, "/admin/two-factor");
+    log_in_with_password(&test_app).await;
+}
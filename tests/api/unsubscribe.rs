@@ -0,0 +1,156 @@
+use sqlx::prelude::FromRow;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_response_redirect_to, create_confirmed_subscriber, path_and_query, spawn_app, TestApp,
+};
+
+#[derive(FromRow)]
+struct Subscription {
+    id: uuid::Uuid,
+    status: String,
+}
+
+async fn publish_newsletter(test_app: &TestApp, cookie: &str) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+}
+
+async fn fetch_subscription(test_app: &TestApp) -> Subscription {
+    sqlx::query_as("SELECT id, status FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch saved subscription.")
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_one_click_unsubscribe_headers() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, &cookie).await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let unsubscribe_link = test_app.get_unsubscribe_link(&email_request);
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+    assert!(body["Headers"].as_array().unwrap().iter().any(|h| h["Name"]
+        == "List-Unsubscribe-Post"
+        && h["Value"] == "List-Unsubscribe=One-Click"));
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_no_longer_receive_newsletters() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, &cookie).await;
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    drop(mock_guard);
+    let unsubscribe_link = test_app.get_unsubscribe_link(&email_request);
+
+    let response = test_app
+        .post_unsubscribe(&path_and_query(unsubscribe_link))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(fetch_subscription(&test_app).await.status, "unsubscribed");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, &cookie).await;
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_with_an_invalid_token_are_rejected() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let subscription = fetch_subscription(&test_app).await;
+
+    let response = test_app
+        .post_unsubscribe(&format!(
+            "/subscriptions/unsubscribe?subscriber_id={}&token={}",
+            subscription.id,
+            "a".repeat(64)
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(fetch_subscription(&test_app).await.status, "confirmed");
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_stay_suppressed_when_they_unsubscribe() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let cookie = test_app.login_and_get_cookie().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, &cookie).await;
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = test_app.get_unsubscribe_link(&email_request);
+    sqlx::query("UPDATE subscriptions SET status = 'suppressed'")
+        .execute(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+
+    let response = test_app
+        .post_unsubscribe(&path_and_query(unsubscribe_link))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(fetch_subscription(&test_app).await.status, "suppressed");
+}
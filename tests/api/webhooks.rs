@@ -0,0 +1,152 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_response_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn subscriber_email(test_app: &TestApp) -> String {
+    let (email,): (String,) = sqlx::query_as("SELECT email FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch saved subscription.");
+    email
+}
+
+async fn subscriber_status(test_app: &TestApp) -> String {
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM subscriptions")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .expect("Failed to fetch saved subscription.");
+    status
+}
+
+fn bounce(email: &str, bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Email": email,
+        "BouncedAt": "2024-11-22T06:00:00Z",
+        "Description": "The server was unable to deliver your message.",
+    })
+}
+
+#[tokio::test]
+async fn webhook_requests_without_valid_credentials_are_rejected() {
+    let test_app = spawn_app().await;
+    let body = bounce("someone@example.com", "HardBounce");
+    let (username, _) = test_app.webhook_credentials();
+
+    for credentials in [None, Some((username, "wrong-password"))] {
+        let response = test_app.post_postmark_webhook(&body, credentials).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn hard_bounces_suppress_the_subscriber() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let email = subscriber_email(&test_app).await;
+
+    let response = test_app
+        .post_postmark_webhook(
+            &bounce(&email, "HardBounce"),
+            Some(test_app.webhook_credentials()),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&test_app).await, "suppressed");
+
+    let (record_type, event_type): (String, Option<String>) =
+        sqlx::query_as("SELECT record_type, event_type FROM email_events WHERE email = $1")
+            .bind(&email)
+            .fetch_one(test_app.app_state.db_pool.as_ref())
+            .await
+            .expect("Failed to fetch email event.");
+    assert_eq!(record_type, "Bounce");
+    assert_eq!(event_type.as_deref(), Some("HardBounce"));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let cookie = test_app.login_and_get_cookie().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = test_app
+        .post_newsletter_with_cookie(&newsletter_request_body, &cookie)
+        .await;
+    assert_response_redirect_to(response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn bounces_suppress_the_subscriber_whatever_the_case_of_the_address() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let email = subscriber_email(&test_app).await;
+
+    let response = test_app
+        .post_postmark_webhook(
+            &bounce(&email.to_uppercase(), "HardBounce"),
+            Some(test_app.webhook_credentials()),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&test_app).await, "suppressed");
+}
+
+#[tokio::test]
+async fn spam_complaints_suppress_the_subscriber() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let email = subscriber_email(&test_app).await;
+
+    let response = test_app
+        .post_postmark_webhook(
+            &serde_json::json!({
+                "RecordType": "SpamComplaint",
+                "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+                "Email": email,
+                "BouncedAt": "2024-11-22T06:00:00Z",
+            }),
+            Some(test_app.webhook_credentials()),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&test_app).await, "suppressed");
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_without_suppressing_the_subscriber() {
+    let test_app = spawn_app().await;
+    let app = test_app.app().await;
+    create_confirmed_subscriber(app.clone(), &test_app).await;
+    let email = subscriber_email(&test_app).await;
+
+    let response = test_app
+        .post_postmark_webhook(
+            &bounce(&email, "SoftBounce"),
+            Some(test_app.webhook_credentials()),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&test_app).await, "confirmed");
+
+    let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM email_events")
+        .fetch_one(test_app.app_state.db_pool.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}